@@ -1,14 +1,14 @@
-#version 330
+#version 330 core
 
 in VS_OUTPUT {
-    vec2 textCoord;
+    vec2 texCoord;
 } IN;
 
 out vec4 Color;
 uniform sampler2D fontTexture;
+uniform vec4 textColor;
 
 void main()
 {
-	//Color = vec4(1,1,1,0);
-	Color = vec4(1,1,1, texture2D(fontTexture, IN.textCoord).r);
+	Color = vec4(textColor.rgb, textColor.a * texture(fontTexture, IN.texCoord).r);
 }
@@ -1,17 +1,18 @@
-#version 330
+#version 330 core
 
-layout (location = 0) in vec4 position;
-layout (location = 1) in vec2 texture;
+layout (location = 0) in vec2 position;
+layout (location = 1) in vec2 texCoord;
 
 out VS_OUTPUT {
-    vec2 textCoord;
+    vec2 texCoord;
 } OUT;
 
-uniform mat4 cameraToClipMatrix;
-uniform mat4 modelToCameraMatrix;
+// Positions are in pixels from the top left corner of the window
+uniform vec2 screenSize;
 
 void main()
 {
-	OUT.textCoord = texture;
-	gl_Position = cameraToClipMatrix * (modelToCameraMatrix * position);
+	OUT.texCoord = texCoord;
+	vec2 clip = position / screenSize * 2.0 - 1.0;
+	gl_Position = vec4(clip.x, -clip.y, 0.0, 1.0);
 }
@@ -0,0 +1,14 @@
+#version 330 core
+#include "playground/shadertoy.glsl"
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord)
+{
+	vec2 uv = fragCoord / iResolution.xy;
+	vec2 mouse = iMouse.xy / iResolution.xy;
+	float t = iTime * 0.5;
+	float v = sin(uv.x * 10.0 + t)
+		+ sin((uv.y * 10.0 + t) * 0.5)
+		+ sin(length(uv - mouse) * 12.0 - t * 2.0);
+	vec3 color = 0.5 + 0.5 * cos(v + vec3(0.0, 2.0, 4.0));
+	fragColor = vec4(color, 1.0);
+}
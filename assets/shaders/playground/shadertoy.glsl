@@ -0,0 +1,18 @@
+// The inputs of a Shadertoy image shader. Include this after the #version
+// line and define mainImage:
+//
+//     void mainImage(out vec4 fragColor, in vec2 fragCoord)
+
+uniform vec3 iResolution;  // window size in pixels, z is the pixel aspect ratio
+uniform float iTime;       // seconds since the shader was loaded
+uniform int iFrame;        // frames drawn since the shader was loaded
+uniform vec4 iMouse;       // xy: position while a button is down, zw: where it was pressed
+
+out vec4 shadertoyColor;
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord);
+
+void main()
+{
+	mainImage(shadertoyColor, gl_FragCoord.xy);
+}
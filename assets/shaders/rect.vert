@@ -0,0 +1,12 @@
+#version 330 core
+
+layout (location = 0) in vec2 position;
+
+// Positions are in pixels from the top left corner of the window
+uniform vec2 screenSize;
+
+void main()
+{
+	vec2 clip = position / screenSize * 2.0 - 1.0;
+	gl_Position = vec4(clip.x, -clip.y, 0.0, 1.0);
+}
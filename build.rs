@@ -2,6 +2,7 @@ extern crate walkdir;
 
 use std::env;
 use std::fs::{self, DirBuilder};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
@@ -9,6 +10,13 @@ fn main() {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
 
+    // Cargo scans the directory recursively, so editing, adding or removing
+    // an asset copies and embeds them again. Naming any file turns off the
+    // default of rerunning on every change to the package, build.rs itself
+    // has to be listed too.
+    println!("cargo:rerun-if-changed=assets");
+    println!("cargo:rerun-if-changed=build.rs");
+
     // locate executable path even if the project is in workspace
 
     let executable_path = locate_target_dir_from_output_dir(&out_dir)
@@ -19,6 +27,13 @@ fn main() {
         &manifest_dir.join("assets"),
         &executable_path.join("assets"),
     );
+
+    if env::var("CARGO_FEATURE_EMBEDDED_ASSETS").is_ok() {
+        write_embedded_assets(
+            &manifest_dir.join("assets"),
+            &out_dir.join("embedded_assets.rs"),
+        );
+    }
 }
 
 fn locate_target_dir_from_output_dir(mut target_dir_search: &Path) -> Option<&Path> {
@@ -56,4 +71,28 @@ fn copy(from: &Path, to: &Path) {
             }
         }
     }
-}
\ No newline at end of file
+}
+// Generates a table of (resource name, file contents) pairs that the
+// `embedded-assets` feature includes into the binary.
+fn write_embedded_assets(from: &Path, to: &Path) {
+    let mut entries = vec!();
+    for entry in WalkDir::new(from).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry.unwrap();
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel_path = entry.path().strip_prefix(from).unwrap();
+        let resource_name = rel_path.iter()
+            .map(|part| part.to_str().expect("asset path is not valid UTF-8"))
+            .collect::<Vec<_>>()
+            .join("/");
+        entries.push((resource_name, entry.path().to_owned()));
+    }
+
+    let mut file = fs::File::create(to).expect("failed to create embedded assets table");
+    writeln!(file, "pub static FILES: &[(&str, &[u8])] = &[").unwrap();
+    for (resource_name, path) in entries {
+        writeln!(file, "    ({:?}, include_bytes!({:?})),", resource_name, path).unwrap();
+    }
+    writeln!(file, "];").unwrap();
+}
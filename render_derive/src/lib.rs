@@ -9,13 +9,14 @@ use quote::quote;
 use syn::{parse_macro_input, DeriveInput};
 
 
-#[proc_macro_derive(VertexAttribPointers, attributes(location))]
+#[proc_macro_derive(VertexAttribPointers, attributes(location, divisor))]
 pub fn vertex_attrib_pointers_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
     let generics = &input.generics;
     let where_clause = &input.generics.where_clause;
     let fields_vertex_attrib_pointer = generate_vertex_attrib_pointer_calls(&input.data);
+    let divisor = instance_divisor(&input.data);
 
     let gen = quote!{
         impl #name #generics #where_clause {
@@ -27,6 +28,14 @@ pub fn vertex_attrib_pointers_derive(input: proc_macro::TokenStream) -> proc_mac
                 #(#fields_vertex_attrib_pointer)*
             }
         }
+
+        impl #generics ::rustgl::render::vertex::VertexLayout for #name #generics #where_clause {
+            const DIVISOR: usize = #divisor;
+
+            fn vertex_attrib_pointers(gl: &::gl::Gl) {
+                #name::vertex_attrib_pointers(gl)
+            }
+        }
     };
     proc_macro::TokenStream::from(gen)
 }
@@ -42,22 +51,59 @@ fn generate_vertex_attrib_pointer_calls(body: &syn::Data) -> Vec<TokenStream> {
     }
 }
 
+/// The smallest `#[divisor = ?]` of the fields, or 0 if any field advances
+/// per vertex.
+fn instance_divisor(body: &syn::Data) -> usize {
+    match body {
+        &syn::Data::Struct(ref s) => {
+            s.fields.iter()
+                .map(|field| {
+                    let field_name = field.ident.as_ref().map(|i| format!("{}", i)).unwrap_or_default();
+                    parse_usize_attribute(field, &field_name, "divisor").unwrap_or(0)
+                })
+                .min()
+                .unwrap_or(0)
+        },
+        _ => 0,
+    }
+}
+
 fn generate_struct_field_vertex_attrib_pointer_call(field: &syn::Field) -> TokenStream {
     let field_name = match field.ident {
         Some(ref i) => format!("{}", i),
         None => String::from(""),
     };
-    let location_attr = field.attrs
-        .iter()
-        .filter(|a| a.path.is_ident("location"))
-        .next()
+    let location_value = parse_usize_attribute(field, &field_name, "location")
         .unwrap_or_else(|| panic!(
             "Field {} is missing #[location = ?] attribute", field_name
         ));
 
-    let vals = location_attr.parse_meta().unwrap();
-    let location_value: usize = match vals {
-        syn::Meta::NameValue(syn::MetaNameValue { ref ident, ref lit, .. }) if ident == "location" => (
+    // Fields with a divisor advance once per `divisor` instances instead of
+    // once per vertex
+    let divisor = parse_usize_attribute(field, &field_name, "divisor").map(|divisor| quote! {
+        gl.VertexAttribDivisor(location as ::gl::types::GLuint, #divisor as ::gl::types::GLuint);
+    });
+
+    let field_ty = &field.ty;
+    quote! {
+        let location = #location_value;
+        unsafe {
+            #field_ty::vertex_attrib_pointer(gl, stride, location, offset);
+            #divisor
+        }
+        let offset = offset + ::std::mem::size_of::<#field_ty>();
+    }
+}
+
+fn parse_usize_attribute(field: &syn::Field, field_name: &str, name: &str) -> Option<usize> {
+    let attr = field.attrs
+        .iter()
+        .filter(|a| a.path.is_ident(name))
+        .next()?;
+
+    let vals = attr.parse_meta().unwrap();
+    let value = match vals {
+        syn::Meta::NameValue(syn::MetaNameValue { ref ident, ref lit, .. }) if ident == name => (
                 if let syn::Lit::Str(lit) = lit {
                     usize::from_str_radix(&lit.value(), 10).unwrap()
                 }
@@ -65,18 +111,141 @@ fn generate_struct_field_vertex_attrib_pointer_call(field: &syn::Field) -> Token
                     lit.value() as usize
                 }
                 else {
-                    panic!("Field {} location attribute value is not parseable", field_name)
+                    panic!("Field {} {} attribute value is not parseable", field_name, name)
                 }
             ),
-        _ => panic!("Field {} location attribute value must be a string literal", field_name)
+        _ => panic!("Field {} {} attribute value must be a string literal", field_name, name)
     };
+    Some(value)
+}
 
-    let field_ty = &field.ty;
-    quote! {
-        let location = #location_value;
-        unsafe {
-            #field_ty::vertex_attrib_pointer(gl, stride, location, offset);
-        }
-        let offset = offset + ::std::mem::size_of::<#field_ty>();
+#[proc_macro_derive(UniformBlock)]
+pub fn uniform_block_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let vis = &input.vis;
+    if !input.generics.params.is_empty() {
+        panic!("UniformBlock can not be implemented for generic structs");
+    }
+    let fields: Vec<&syn::Field> = match input.data {
+        syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(ref fields), .. }) => fields.named.iter().collect(),
+        syn::Data::Struct(_) => panic!("UniformBlock can only be implemented for structs with named fields"),
+        syn::Data::Enum(_) => panic!("UniformBlock can not be implemented for enums"),
+        syn::Data::Union(_) => panic!("UniformBlock can not be implemented for a Union"),
+    };
+    if fields.is_empty() {
+        panic!("UniformBlock {} needs at least one field", name);
+    }
+
+    let std140 = quote!(::rustgl::render::uniform_block);
+    let std140_name = syn::Ident::new(&format!("{}Std140", name), name.span());
+    let field_count = fields.len();
+    let padded = |ty: &syn::Type| quote!(::std::mem::size_of::<<#ty as #std140::Std140>::Padded>());
+    let block_size = quote!(<#name as #std140::UniformBlock>::SIZE);
+    let mut companion_fields = vec!();
+    let mut offsets = vec!();
+    let mut padding = vec!();
+    let mut conversions = vec!();
+    let mut assertions = vec!();
+    for (index, field) in fields.iter().enumerate() {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_vis = &field.vis;
+        let field_ty = &field.ty;
+        let padding_name = syn::Ident::new(&format!("_padding{}", index), name.span());
+        let size = padded(field_ty);
+        companion_fields.push(quote! {
+            #padding_name: [u8; #name::STD140_PADDING[#index]],
+            #field_vis #field_name: <#field_ty as #std140::Std140>::Padded,
+        });
+        offsets.push(quote! {
+            offsets[#index] = #std140::align_to(end, <#field_ty as #std140::Std140>::ALIGN);
+            end = offsets[#index] + #size;
+        });
+        // The gap between the end of the previous field and this one
+        let previous_end = if index == 0 {
+            quote!(0)
+        } else {
+            let previous = index - 1;
+            let previous_size = padded(&fields[previous].ty);
+            quote!(#name::STD140_OFFSETS[#previous] + #previous_size)
+        };
+        padding.push(quote! {
+            padding[#index] = #name::STD140_OFFSETS[#index] - (#previous_end);
+        });
+        conversions.push(quote! {
+            #padding_name: [0; #name::STD140_PADDING[#index]],
+            #field_name: #std140::Std140::to_std140(&self.#field_name),
+        });
+        let message = format!("field `{}` of {} is not at its std140 offset", field_name, std140_name);
+        assertions.push(quote! {
+            assert!(::std::mem::offset_of!(#std140_name, #field_name) == #name::STD140_OFFSETS[#index], #message);
+        });
     }
-}
\ No newline at end of file
+    let last = field_count - 1;
+    let last_size = padded(&fields[last].ty);
+    let trailing_padding = syn::Ident::new(&format!("_padding{}", field_count), name.span());
+    let size_message = format!("{} is not as large as the std140 block", std140_name);
+
+    let gen = quote!{
+        #[repr(C)]
+        #[derive(Copy, Clone)]
+        #[allow(dead_code)]
+        #vis struct #std140_name {
+            #(#companion_fields)*
+            #trailing_padding: [u8; #name::STD140_PADDING[#field_count]],
+        }
+
+        impl #name {
+            #[doc(hidden)]
+            const STD140_OFFSETS: [usize; #field_count] = {
+                let mut offsets = [0; #field_count];
+                let mut end = 0;
+                #(#offsets)*
+                let _ = end;
+                offsets
+            };
+
+            #[doc(hidden)]
+            const STD140_PADDING: [usize; #field_count + 1] = {
+                let mut padding = [0; #field_count + 1];
+                #(#padding)*
+                padding[#field_count] = #block_size - #name::STD140_OFFSETS[#last] - #last_size;
+                padding
+            };
+        }
+
+        impl #std140::UniformBlock for #name {
+            type Std140 = #std140_name;
+            const SIZE: usize = #std140::align_to(#name::STD140_OFFSETS[#last] + #last_size, 16);
+
+            fn to_std140(&self) -> #std140_name {
+                #std140_name {
+                    #(#conversions)*
+                    #trailing_padding: [0; #name::STD140_PADDING[#field_count]],
+                }
+            }
+        }
+
+        // Blocks can be nested in other blocks and used in arrays, their
+        // size is already a multiple of 16.
+        impl #std140::Std140 for #name {
+            const ALIGN: usize = 16;
+            type Padded = #std140_name;
+            type ArrayElement = #std140_name;
+
+            fn to_std140(&self) -> #std140_name {
+                #std140::UniformBlock::to_std140(self)
+            }
+
+            fn to_std140_array_element(&self) -> #std140_name {
+                #std140::UniformBlock::to_std140(self)
+            }
+        }
+
+        const _: () = {
+            #(#assertions)*
+            assert!(::std::mem::size_of::<#std140_name>() == #block_size, #size_message);
+        };
+    };
+    proc_macro::TokenStream::from(gen)
+}
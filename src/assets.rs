@@ -0,0 +1,216 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+use crate::render::font::{self, Font, FontData};
+use crate::render::shaders::{self, Program};
+use crate::render::texture::Texture;
+use crate::resources::{self, FontFile, Image, Resources};
+
+/// A shared, reference counted reference to a loaded asset.
+pub struct Handle<T> {
+    inner: Rc<T>,
+}
+
+impl<T> Handle<T> {
+    /// Wraps a value in a handle that is not tracked by any cache.
+    pub fn new(value: T) -> Handle<T> {
+        Handle {
+            inner: Rc::new(value),
+        }
+    }
+
+    /// Whether both handles point at the same loaded asset.
+    pub fn ptr_eq(this: &Handle<T>, other: &Handle<T>) -> bool {
+        Rc::ptr_eq(&this.inner, &other.inner)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// An asset type, its resource path and the defines it was built with, sorted
+/// by name.
+type Key = (TypeId, String, Vec<(String, String)>);
+
+/// Caches loaded assets by type and resource path. The cache only holds weak
+/// references, so an asset is released as soon as the last `Handle` to it is
+/// dropped, and loading it again afterwards goes back to `Resources`.
+pub struct Assets {
+    entries: RefCell<HashMap<Key, Weak<dyn Any>>>,
+}
+
+impl Default for Assets {
+    fn default() -> Assets {
+        Assets::new()
+    }
+}
+
+impl Assets {
+    pub fn new() -> Assets {
+        Assets {
+            entries: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cached asset of type `T` stored under `key`, if it is
+    /// still alive.
+    pub fn get<T: 'static>(&self, key: &str) -> Option<Handle<T>> {
+        self.lookup(&(TypeId::of::<T>(), key.to_owned(), vec!()))
+    }
+
+    /// Finds a live entry, forgetting the entry if it has been released.
+    fn lookup<T: 'static>(&self, key: &Key) -> Option<Handle<T>> {
+        let mut entries = self.entries.borrow_mut();
+        let entry = entries.get(key)?.upgrade();
+        if entry.is_none() {
+            entries.remove(key);
+        }
+        entry
+            .and_then(|entry| entry.downcast::<T>().ok())
+            .map(|inner| Handle { inner })
+    }
+
+    /// Returns the cached asset stored under `key`, or runs `load` and caches
+    /// its result.
+    pub fn get_or_load<T, E, F>(&self, key: &str, load: F) -> Result<Handle<T>, E>
+    where T: 'static, F: FnOnce() -> Result<T, E> {
+        self.get_or_load_variant(key, vec!(), load)
+    }
+
+    fn get_or_load_variant<T, E, F>(&self, key: &str, defines: Vec<(String, String)>, load: F) -> Result<Handle<T>, E>
+    where T: 'static, F: FnOnce() -> Result<T, E> {
+        let key = (TypeId::of::<T>(), key.to_owned(), defines);
+        if let Some(handle) = self.lookup(&key) {
+            return Ok(handle);
+        }
+        // `load` is allowed to load other assets, so the cache must not be
+        // borrowed while it runs.
+        let inner = Rc::new(load()?);
+        let entry: Rc<dyn Any> = inner.clone();
+        self.release_unused();
+        self.entries.borrow_mut().insert(key, Rc::downgrade(&entry));
+        Ok(Handle { inner })
+    }
+
+    /// Forgets about every asset that no longer has a live handle.
+    pub fn release_unused(&self) {
+        self.entries.borrow_mut().retain(|_, entry| entry.upgrade().is_some());
+    }
+
+    /// Number of assets that are currently alive.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().values().filter(|entry| entry.upgrade().is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn shader_source(&self, resources: &Resources, path: &str) -> Result<Handle<CString>, resources::Error> {
+        self.get_or_load(path, || resources.load_cstring(path))
+    }
+
+    pub fn font_faces(&self, resources: &Resources, path: &str) -> Result<Handle<Vec<font_kit::font::Font>>, resources::Error> {
+        self.get_or_load(path, || resources.load_font(path))
+    }
+
+    /// The faces of a font file resolved by a loader thread.
+    pub fn font_file_faces(&self, file: &FontFile) -> Result<Handle<Vec<font_kit::font::Font>>, resources::Error> {
+        self.get_or_load(&file.key(), || file.faces())
+    }
+
+    pub fn font(&self, gl: &gl::Gl, resources: &Resources, path: &str, font_size: f32, log: &slog::Logger) -> Result<Handle<Font>, resources::Error> {
+        self.get_or_load(&font::font_key(path, font_size, font::DEFAULT_CHARS), || {
+            let faces = self.font_faces(resources, path)?;
+            Ok(Font::from_faces(gl, faces, font_size, font::DEFAULT_CHARS, log))
+        })
+    }
+
+    /// Finishes a font prepared by a loader thread, unless the same font is
+    /// already loaded.
+    pub fn font_from_data(&self, gl: &gl::Gl, data: FontData, log: &slog::Logger) -> Result<Handle<Font>, resources::Error> {
+        self.get_or_load(&data.key(), || {
+            let faces = self.font_file_faces(data.file())?;
+            Ok(Font::from_data(gl, faces, data, log))
+        })
+    }
+
+    pub fn program(&self, gl: &gl::Gl, resources: &Resources, name: &str) -> Result<Handle<Program>, shaders::Error> {
+        self.program_variant(gl, resources, name, &[])
+    }
+
+    /// A program compiled with extra defines. Variants are cached by name and
+    /// defines, the order the defines are given in does not matter. Defining
+    /// a name twice is an error.
+    pub fn program_variant(&self, gl: &gl::Gl, resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Handle<Program>, shaders::Error> {
+        let mut sorted = defines.to_vec();
+        sorted.sort();
+        let variant = sorted.iter().map(|&(define, value)| (define.to_owned(), value.to_owned())).collect();
+        self.get_or_load_variant(name, variant, || Program::from_res_with_defines(gl, resources, name, &sorted).map(|program| *program))
+    }
+
+    pub fn texture(&self, gl: &gl::Gl, resources: &Resources, path: &str) -> Result<Handle<Texture>, resources::Error> {
+        self.get_or_load(path, || {
+            let image = resources.load_image(path)?;
+            Ok(Texture::from_image(gl, &image))
+        })
+    }
+
+    /// Uploads an image decoded by a loader thread from the resource `path`,
+    /// unless the texture for `path` is already loaded.
+    pub fn texture_from_image(&self, gl: &gl::Gl, path: &str, image: &Image) -> Result<Handle<Texture>, resources::Error> {
+        self.get_or_load(path, || Ok(Texture::from_image(gl, image)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(defines: &[(&str, &str)]) -> Vec<(String, String)> {
+        defines.iter().map(|&(define, value)| (define.to_owned(), value.to_owned())).collect()
+    }
+
+    #[test]
+    fn variants_are_told_apart_by_their_defines() {
+        let assets = Assets::new();
+        let both = assets.get_or_load_variant("p", variant(&[("A", "1"), ("B", "2")]), || Ok::<_, ()>(1)).unwrap();
+        // Would have had the same key as the first when the defines were
+        // joined into the name
+        let one = assets.get_or_load_variant("p", variant(&[("A", "1#B=2")]), || Ok::<_, ()>(2)).unwrap();
+        let again = assets.get_or_load_variant("p", variant(&[("A", "1"), ("B", "2")]), || Ok::<_, ()>(3)).unwrap();
+        assert_eq!((*both, *one, *again), (1, 2, 1));
+        assert!(Handle::ptr_eq(&both, &again));
+        assert!(assets.get::<i32>("p").is_none());
+    }
+
+    #[test]
+    fn assets_are_released_with_their_last_handle() {
+        let assets = Assets::new();
+        let handle = assets.get_or_load("a", || Ok::<_, ()>(String::from("a"))).unwrap();
+        assert_eq!(assets.len(), 1);
+        assert!(assets.get::<String>("a").is_some());
+        drop(handle);
+        assert!(assets.is_empty());
+        assert!(assets.get::<String>("a").is_none());
+        // Looking a released asset up forgets about it
+        assert_eq!(assets.entries.borrow().len(), 0);
+    }
+}
@@ -0,0 +1,70 @@
+//! Checks the shader programs of an asset directory without a GL context.
+//!
+//! ```text
+//! check-shaders [<assets dir>] [<program>...]
+//! ```
+//!
+//! Checks every program in the manifest, or just the named ones, and exits
+//! with a non-zero status if any of them has errors. The assets directory
+//! defaults to `assets`.
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use rustgl::render::shaders::check;
+use rustgl::resources::{DirectorySource, Resources};
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let assets = args.next().unwrap_or_else(|| "assets".into());
+    let only = args.collect::<Vec<_>>();
+    if assets.starts_with('-') {
+        eprintln!("usage: check-shaders [<assets dir>] [<program>...]");
+        process::exit(2);
+    }
+
+    let mut resources = Resources::new();
+    resources.mount("", DirectorySource::new(Path::new(&assets)));
+    let manifest = match resources.load_manifest("manifest.ron") {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("check-shaders: {}", e);
+            process::exit(1);
+        },
+    };
+    for name in &only {
+        if !manifest.programs.contains_key(name) {
+            eprintln!("check-shaders: no program {} in the manifest", name);
+            process::exit(1);
+        }
+    }
+
+    let mut failed = 0;
+    for (name, entry) in &manifest.programs {
+        if !only.is_empty() && !only.contains(name) {
+            continue;
+        }
+        let defines = entry.defines.iter()
+            .map(|(define, value)| (define.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        match check::check_program(&resources, &entry.path, &defines) {
+            Ok(report) => {
+                if !report.diagnostics.is_empty() {
+                    println!("{}\n", report.render());
+                }
+                if report.has_errors() {
+                    failed += 1;
+                }
+            },
+            Err(e) => {
+                println!("error: {}\n", e);
+                failed += 1;
+            },
+        }
+    }
+    if failed > 0 {
+        eprintln!("check-shaders: {} program(s) with errors", failed);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,62 @@
+//! Packs an asset directory into a single pak archive.
+//!
+//! ```text
+//! pack-assets [--store] <assets dir> <output.pak>
+//! ```
+//!
+//! Entries are deflate compressed unless `--store` is given. Ship the archive
+//! as `assets.pak` next to the executable and `Resources` picks it up.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use walkdir::WalkDir;
+
+use rustgl::resources::pak::{Compression, PakWriter};
+
+fn main() {
+    let mut compression = Compression::Deflate;
+    let mut paths = vec!();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--store" => compression = Compression::None,
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        eprintln!("usage: pack-assets [--store] <assets dir> <output.pak>");
+        process::exit(2);
+    }
+
+    if let Err(e) = pack(Path::new(&paths[0]), Path::new(&paths[1]), compression) {
+        eprintln!("pack-assets: {}", e);
+        process::exit(1);
+    }
+}
+
+fn pack(from: &Path, to: &Path, compression: Compression) -> io::Result<()> {
+    let mut writer = PakWriter::new(io::BufWriter::new(fs::File::create(to)?))?;
+    let output = to.canonicalize()?;
+    for entry in WalkDir::new(from).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry?;
+        if !entry.file_type().is_file() || entry.path().canonicalize()? == output {
+            continue;
+        }
+        let rel_path = entry.path().strip_prefix(from).unwrap();
+        let resource_name = rel_path.iter()
+            .map(|part| part.to_str().ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not valid UTF-8", rel_path),
+            )))
+            .collect::<io::Result<Vec<_>>>()?
+            .join("/");
+        let data = fs::read(entry.path())?;
+        writer.add(&resource_name, &data, compression)?;
+        println!("{} ({} bytes)", resource_name, data.len());
+    }
+    writer.finish()?;
+    Ok(())
+}
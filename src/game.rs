@@ -1,18 +1,26 @@
+pub mod playground;
 pub mod scenes;
+pub mod shader_watcher;
+pub mod tweak_panel;
 
-use glutin::dpi::LogicalSize;
 use crate::render::shaders::Program;
 use std::collections::HashMap;
 use std::collections::VecDeque;
-use crate::resources::Resources;
+use crate::resources::{Image, Loader, Manifest, Resources, Ticket};
+use std::sync::Arc;
+use crate::assets::{Assets, Handle};
 use slog::{info, debug, trace, warn, error};
 use slog::o;
 use nalgebra;
 
 use crate::render::viewport::Viewport;
 use crate::render::color_buffer::ColorBuffer;
-use crate::render::font::Font;
+use crate::render::font::{self, Font, FontData};
+use crate::render::texture::Texture;
+use playground::Playground;
 use scenes::Scene;
+use shader_watcher::ShaderWatcher;
+use tweak_panel::TweakPanel;
 
 pub struct Game<'a> {
 
@@ -21,26 +29,41 @@ pub struct Game<'a> {
     window: &'a glutin::GlWindow,
     event_loop: &'a mut glutin::EventsLoop,
     scenes: Vec<Box<Scene>>,
-    programs: HashMap<gl::types::GLuint, Box<Program>>,
+    programs: HashMap<gl::types::GLuint, Handle<Program>>,
     log: slog::Logger,
     gl: gl::Gl,
     viewport: Viewport,
     color_buffer: ColorBuffer,
-    font: Font,
+    fonts: HashMap<String, Handle<Font>>,
+    pending_fonts: HashMap<String, Ticket<FontData>>,
+    textures: HashMap<String, Handle<Texture>>,
+    pending_textures: HashMap<String, Ticket<Image>>,
+    font_log: slog::Logger,
+    manifest: Manifest,
+    assets: Assets,
+    loader: Loader,
+    resources: Arc<Resources>,
+    shader_watcher: Option<ShaderWatcher>,
+    playground: Option<Playground>,
+    tweak_panel: Option<TweakPanel>,
 }
 
 impl<'a> Game<'a> {
 
-    pub fn new(gl: &gl::Gl, resources: &Resources, gl_window: &'a glutin::GlWindow, event_loop: &'a mut glutin::EventsLoop, log: &'a slog::Logger) -> Result<Box<Game<'a>>, failure::Error>  {
+    pub fn new(gl: &gl::Gl, resources: Arc<Resources>, gl_window: &'a glutin::GlWindow, event_loop: &'a mut glutin::EventsLoop, log: &'a slog::Logger) -> Result<Box<Game<'a>>, failure::Error>  {
 
         info!(log, "Creating new Game Engine");;
 
-        let triangle = Program::from_res(gl, resources, "shaders/triangle_test")?;
-        let test_scene = Scene::with_program(gl, &triangle);
-        let mut programs = HashMap::new();
-        programs.insert(triangle.id(), triangle);
-        let dpi = gl_window.get_hidpi_factor();
+        let manifest = resources.load_manifest("manifest.ron")?;
         let log = log.new(o!("module" => "game"));
+        let shader_watcher = match ShaderWatcher::new() {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!(log, "Shader hot reloading is disabled: {}", e);
+                None
+            }
+        };
+        let dpi = gl_window.get_hidpi_factor();
         let font_log = log.new(o!("sub_module" => "fonts"));
         let size = gl_window.get_inner_size().unwrap();
         let physical_size = size.to_physical(dpi);
@@ -52,20 +75,48 @@ impl<'a> Game<'a> {
                 height = h;
             }
         }
-        let game = Box::new(Game {
+        let mut game = Box::new(Game {
             running: true,
             key_presses: VecDeque::new(),
             window: &gl_window,
             event_loop: event_loop,
-            scenes: vec!(test_scene),
-            programs: programs,
+            scenes: vec!(),
+            programs: HashMap::new(),
             log: log,
             gl: gl.clone(),
             viewport: Viewport::from_dimensions(width as i32, height as i32, dpi),
             color_buffer: ColorBuffer::from_color(nalgebra::Vector3::new(0.3, 0.3, 0.5)),
-            font: Font::from_resource(gl, resources, "fonts/DigitalDream.ttf", 32.0, &font_log),
+            fonts: HashMap::new(),
+            pending_fonts: HashMap::new(),
+            textures: HashMap::new(),
+            pending_textures: HashMap::new(),
+            font_log: font_log,
+            manifest: manifest,
+            assets: Assets::new(),
+            loader: Loader::new(resources.clone(), 2),
+            resources: resources,
+            shader_watcher: shader_watcher,
+            playground: None,
+            tweak_panel: None,
             });
 
+        game.load_manifest_assets();
+        let triangle = game.program("triangle")?;
+        game.scenes.push(Scene::with_program(gl, &triangle));
+        game.tweak_panel = match (game.program("rect"), game.program("font")) {
+            (Ok(rect), Ok(font)) => match TweakPanel::new(gl, rect, font) {
+                Ok(tweak_panel) => Some(tweak_panel),
+                Err(e) => {
+                    warn!(game.log, "The tweak panel is disabled: {}", e);
+                    None
+                },
+            },
+            (Err(e), _) | (_, Err(e)) => {
+                warn!(game.log, "The tweak panel is disabled: {}", e);
+                None
+            },
+        };
+
         return Ok(game);
     }
 
@@ -80,27 +131,197 @@ impl<'a> Game<'a> {
             for event in &events {
                 self.handle_event(event);
             }
+            self.reload_changed_programs();
+            self.poll_loading();
             while let Some(key_press) = self.key_presses.pop_front() {
                 match key_press {
                     glutin::VirtualKeyCode::Escape => {
                         self.exit();
                     },
+                    glutin::VirtualKeyCode::F1 => {
+                        if let Some(ref mut panel) = self.tweak_panel {
+                            panel.toggle();
+                        }
+                    },
+                    glutin::VirtualKeyCode::F2 => {
+                        if let Some(ref panel) = self.tweak_panel {
+                            match panel.save(&self.resources) {
+                                Ok(path) => info!(self.log, "Saved tweaks to {:?}", path),
+                                Err(e) => error!(self.log, "Failed to save tweaks: {}", e),
+                            }
+                        }
+                    },
                     _ => {
                         debug!(self.log, "Unhandled keypress: {:?}", key_press);
                     }
                 }
             }
             self.color_buffer.clear(&self.gl);
-            for scene in &self.scenes {
-                let program = self.programs.get(&scene.program_id()).unwrap();
-                program.activate();
-                scene.draw();
+            if self.is_loading() {
+                // Nothing but the clear color until everything has streamed in
+                self.window.swap_buffers().unwrap();
+                continue;
+            }
+            if let Some(ref mut playground) = self.playground {
+                playground.reload_if_changed(&self.resources);
+                let font = self.fonts.get("mono").map(|font| &**font);
+                if let Err(e) = playground.draw(&self.viewport, font) {
+                    error!(self.log, "Failed to draw the playground: {}", e);
+                }
+            } else {
+                for scene in &self.scenes {
+                    let program = self.programs.get(scene.program_id()).unwrap();
+                    program.activate();
+                    scene.draw()?;
+                }
             }
+            self.draw_tweak_panel();
             self.window.swap_buffers().unwrap();
         }
         Ok(())
     }
 
+    /// Keeps the tweak panel on the program being shown, the playground's or
+    /// the first scene's, and draws it on top.
+    fn draw_tweak_panel(&'_ mut self) {
+        let panel = match self.tweak_panel {
+            Some(ref mut panel) => panel,
+            None => return,
+        };
+        let program = match self.playground {
+            Some(ref playground) => playground.program(),
+            None => {
+                let programs = &self.programs;
+                self.scenes.first().and_then(|scene| programs.get(scene.program_id())).map(|program| &**program)
+            },
+        };
+        if let Some(program) = program {
+            if let Err(e) = panel.sync(program, &self.resources) {
+                warn!(self.log, "{}", e);
+            }
+        }
+        if let Some(font) = self.fonts.get("mono") {
+            if let Err(e) = panel.draw(font, (self.viewport.w, self.viewport.h)) {
+                error!(self.log, "Failed to draw the tweak panel: {}", e);
+            }
+        }
+    }
+
+    fn reload_changed_programs(&'_ mut self) {
+        let changed = match self.shader_watcher {
+            Some(ref watcher) => watcher.changed_programs(),
+            None => return,
+        };
+        for program_id in changed {
+            let program = match self.programs.get(&program_id) {
+                Some(program) => program,
+                None => continue,
+            };
+            match program.reload(&self.resources) {
+                Ok(()) => info!(self.log, "Reloaded program {}", program.name().unwrap_or("")),
+                Err(e) => {
+                    error!(self.log, "Keeping previous program: {}", e);
+                    continue;
+                },
+            }
+            // The new sources may include files that weren't watched yet
+            if let Some(ref mut watcher) = self.shader_watcher {
+                if let Err(e) = watcher.watch(&self.resources, program) {
+                    warn!(self.log, "Failed to watch shader sources: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Replaces the scenes with a fragment shader from `assets/shaders` drawn
+    /// over the whole window, see `Playground`. `file` is the name of the
+    /// shader with or without its `.frag` extension.
+    pub fn open_playground(&'_ mut self, file: &str) -> Result<(), failure::Error> {
+        let mut fragment = format!("shaders/{}", file);
+        if !fragment.ends_with(".frag") {
+            fragment.push_str(".frag");
+        }
+        let text_program = self.program("font")?;
+        let log = self.log.new(o!("sub_module" => "playground"));
+        self.playground = Some(Playground::new(&self.gl, &self.resources, &fragment, text_program, &log)?);
+        Ok(())
+    }
+
+    /// Looks up a program declared in the manifest, loading it on first use.
+    pub fn program(&'_ mut self, name: &str) -> Result<Handle<Program>, failure::Error> {
+        let entry = self.manifest.program(name)?;
+        let defines = entry.defines.iter()
+            .map(|(define, value)| (define.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        let program = self.assets.program_variant(&self.gl, &self.resources, &entry.path, &defines)?;
+        if !self.programs.contains_key(&program.id()) {
+            if let Some(ref mut watcher) = self.shader_watcher {
+                if let Err(e) = watcher.watch(&self.resources, &program) {
+                    warn!(self.log, "Failed to watch shader sources: {}", e);
+                }
+            }
+            self.programs.insert(program.id(), program.clone());
+        }
+        Ok(program)
+    }
+
+    /// Looks up a font declared in the manifest, `None` while it is still
+    /// loading.
+    pub fn font(&'_ self, name: &str) -> Option<&Handle<Font>> {
+        self.fonts.get(name)
+    }
+
+    /// Looks up a texture declared in the manifest, `None` while it is still
+    /// loading.
+    pub fn texture(&'_ self, name: &str) -> Option<&Handle<Texture>> {
+        self.textures.get(name)
+    }
+
+    /// Queues every font and texture of the manifest on the loader threads.
+    fn load_manifest_assets(&'_ mut self) {
+        for (name, entry) in &self.manifest.fonts {
+            let font_log = self.font_log.clone();
+            let source = entry.source();
+            let size = entry.size;
+            let chars = entry.chars.clone().unwrap_or_else(|| font::DEFAULT_CHARS.into());
+            let ticket = self.loader.load(move |resources| FontData::load(resources, &source, size, &chars, &font_log));
+            self.pending_fonts.insert(name.clone(), ticket);
+        }
+        for (name, entry) in &self.manifest.textures {
+            let path = entry.path.clone();
+            let ticket = self.loader.load(move |resources| resources.load_image(&path));
+            self.pending_textures.insert(name.clone(), ticket);
+        }
+    }
+
+    /// Takes over finished loads. An asset that failed to load is logged
+    /// and left out, `font` and `texture` keep returning `None` for it.
+    fn poll_loading(&'_ mut self) {
+        for (name, result) in poll_tickets(&mut self.pending_fonts) {
+            match result.and_then(|data| self.assets.font_from_data(&self.gl, data, &self.font_log)) {
+                Ok(font) => {
+                    self.fonts.insert(name.clone(), font);
+                    debug!(self.log, "Finished loading font {}", name);
+                },
+                Err(e) => error!(self.log, "Failed to load font {}: {}", name, e),
+            }
+        }
+        for (name, result) in poll_tickets(&mut self.pending_textures) {
+            let path = &self.manifest.textures[&name].path;
+            match result.and_then(|image| self.assets.texture_from_image(&self.gl, path, &image)) {
+                Ok(texture) => {
+                    self.textures.insert(name.clone(), texture);
+                    debug!(self.log, "Finished loading texture {}", name);
+                },
+                Err(e) => error!(self.log, "Failed to load texture {}: {}", name, e),
+            }
+        }
+    }
+
+    fn is_loading(&'_ self) -> bool {
+        !self.pending_fonts.is_empty() || !self.pending_textures.is_empty()
+    }
+
     fn handle_event(&'_ mut self, e: &glutin::Event) {
         if let glutin::Event::WindowEvent { event, .. } = e {
             self.handle_window_event(event);
@@ -125,11 +346,36 @@ impl<'a> Game<'a> {
                 println!("The close button was pressed; stopping");
                 self.exit();
             },
-            Resized(LogicalSize { width, height }) => {
-                self.viewport.change_size(*width as i32, *height as i32);
+            Resized(size) => {
+                // The viewport, and gl_FragCoord with it, counts physical pixels
+                let (width, height): (u32, u32) = size.to_physical(self.viewport.dpi).into();
+                self.viewport.change_size(width as i32, height as i32);
                 self.viewport.set_used(&self.gl);
                 println!("The window was resized to {}x{}", width, height);
             },
+            CursorMoved { position, .. } => {
+                let position = position.to_physical(self.viewport.dpi);
+                let (x, y) = (position.x as f32, position.y as f32);
+                let dragging = self.tweak_panel.as_mut().map_or(false, |panel| panel.cursor_moved(x, y));
+                if let Some(ref mut playground) = self.playground {
+                    if !dragging {
+                        playground.cursor_moved(x, y, self.viewport.h as f32);
+                    }
+                }
+            },
+            MouseInput { state, button: glutin::MouseButton::Left, .. } => {
+                let taken = self.tweak_panel.as_mut().map_or(false, |panel| match state {
+                    glutin::ElementState::Pressed => panel.mouse_pressed(),
+                    glutin::ElementState::Released => panel.mouse_released(),
+                });
+                if let Some(ref mut playground) = self.playground {
+                    match state {
+                        glutin::ElementState::Pressed if !taken => playground.mouse_pressed(),
+                        glutin::ElementState::Released => playground.mouse_released(),
+                        _ => (),
+                    }
+                }
+            },
             glutin::WindowEvent::KeyboardInput {
                 input: glutin::KeyboardInput {
                     state: glutin::ElementState::Released,
@@ -146,3 +392,17 @@ impl<'a> Game<'a> {
     }
 
 }
+
+/// Removes and returns the results of every finished ticket.
+fn poll_tickets<T>(pending: &mut HashMap<String, Ticket<T>>) -> Vec<(String, Result<T, crate::resources::Error>)> {
+    let mut finished = vec!();
+    for (name, ticket) in pending.iter_mut() {
+        if let Some(result) = ticket.poll() {
+            finished.push((name.clone(), result));
+        }
+    }
+    for (name, _) in &finished {
+        pending.remove(name);
+    }
+    finished
+}
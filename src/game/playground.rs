@@ -0,0 +1,179 @@
+use std::time::Instant;
+
+use nalgebra::{Vector2, Vector3, Vector4};
+use slog::{info, warn, error};
+
+use crate::assets::Handle;
+use crate::render::array_buffer::{Topology, VertexArray};
+use crate::render::font::Font;
+use crate::render::ring_buffer;
+use crate::render::shaders::Program;
+use crate::render::text::TextRenderer;
+use crate::render::viewport::Viewport;
+use crate::resources::Resources;
+use super::shader_watcher::ShaderWatcher;
+
+/// The vertex stage shared by every playground shader.
+const FULLSCREEN_VERTEX_SHADER: &str = "shaders/playground/fullscreen.vert";
+
+/// The playground has a single program, so it goes by this id in its own
+/// watcher whether or not the program exists.
+const WATCH_ID: gl::types::GLuint = 0;
+
+/// Draws a fragment shader over the whole window the way Shadertoy does and
+/// rebuilds it whenever its files change. A shader that fails to build leaves
+/// the last working one running and its errors are printed on top.
+///
+/// The shader gets these uniforms when it declares them, see
+/// `shaders/playground/shadertoy.glsl`:
+///
+/// - `vec3 iResolution`, the window size in pixels
+/// - `float iTime`, seconds since the playground was opened
+/// - `int iFrame`, the number of frames drawn
+/// - `vec4 iMouse`, the cursor position while a button is held in `xy` and
+///   where it was pressed in `zw`, negated once it is released
+pub struct Playground {
+    gl: gl::Gl,
+    fragment: String,
+    program: Option<Box<Program>>,
+    error: Option<String>,
+    text_program: Handle<Program>,
+    text: TextRenderer,
+    // The triangle comes from gl_VertexID, but drawing needs a bound VAO
+    triangle: VertexArray,
+    watcher: Option<ShaderWatcher>,
+    start: Instant,
+    frame: i32,
+    cursor: Vector2<f32>,
+    mouse: Vector4<f32>,
+    mouse_down: bool,
+    log: slog::Logger,
+}
+
+impl Playground {
+    /// Opens the fragment shader `fragment`, a resource name such as
+    /// `shaders/plasma.frag`. `text_program` draws the error messages.
+    pub fn new(gl: &gl::Gl, resources: &Resources, fragment: &str, text_program: Handle<Program>, log: &slog::Logger) -> Result<Playground, ring_buffer::Error> {
+        let watcher = match ShaderWatcher::new() {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!(log, "Playground hot reloading is disabled: {}", e);
+                None
+            }
+        };
+        let mut triangle = VertexArray::new(gl);
+        triangle.set_vertex_count(3);
+        triangle.set_topology(Topology::Triangles);
+        let mut playground = Playground {
+            gl: gl.clone(),
+            fragment: fragment.into(),
+            program: None,
+            error: None,
+            text_program,
+            text: TextRenderer::new(gl)?,
+            triangle,
+            watcher,
+            start: Instant::now(),
+            frame: 0,
+            cursor: Vector2::zeros(),
+            mouse: Vector4::zeros(),
+            mouse_down: false,
+            log: log.clone(),
+        };
+        playground.build(resources);
+        Ok(playground)
+    }
+
+    /// The last shader that built.
+    pub fn program(&self) -> Option<&Program> {
+        self.program.as_deref()
+    }
+
+    /// The message of the last failed build, `None` once the shader builds.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Rebuilds the shader if one of its files changed on disk.
+    pub fn reload_if_changed(&mut self, resources: &Resources) {
+        let changed = match self.watcher {
+            Some(ref watcher) => !watcher.changed_programs().is_empty(),
+            None => false,
+        };
+        if changed {
+            self.build(resources);
+        }
+    }
+
+    fn build(&mut self, resources: &Resources) {
+        let stage_files = [FULLSCREEN_VERTEX_SHADER, self.fragment.as_str()];
+        let watched = match Program::from_stage_files(&self.gl, resources, &self.fragment, &stage_files, &[]) {
+            Ok(program) => {
+                info!(self.log, "Built playground shader {}", self.fragment);
+                let watched = program.resource_names();
+                self.program = Some(program);
+                self.error = None;
+                watched
+            },
+            Err(e) => {
+                error!(self.log, "Keeping previous playground shader: {}", e);
+                self.error = Some(e.to_string());
+                // Files that were just added or broken aren't known to any
+                // program yet, so at least keep an eye on the stages
+                stage_files.iter().map(|&file| file.to_owned()).collect()
+            },
+        };
+        if let Some(ref mut watcher) = self.watcher {
+            if let Err(e) = watcher.watch_resources(resources, WATCH_ID, &watched) {
+                warn!(self.log, "Failed to watch playground sources: {}", e);
+            }
+        }
+    }
+
+    /// Draws the shader over the whole viewport, followed by the last error
+    /// if there is one and `font` is loaded.
+    pub fn draw(&mut self, viewport: &Viewport, font: Option<&Font>) -> Result<(), failure::Error> {
+        if let Some(ref program) = self.program {
+            program.activate();
+            let resolution = Vector3::new(viewport.w as f32, viewport.h as f32, 1.0);
+            program.set_uniform_if_active("iResolution", &resolution)?;
+            program.set_uniform_if_active("iTime", &duration_secs(self.start.elapsed()))?;
+            program.set_uniform_if_active("iFrame", &self.frame)?;
+            program.set_uniform_if_active("iMouse", &self.mouse)?;
+            self.triangle.draw()?;
+            self.frame += 1;
+        }
+        if let (Some(error), Some(font)) = (self.error.as_ref(), font) {
+            let color = Vector4::new(1.0, 0.35, 0.3, 1.0);
+            self.text.draw(&self.text_program, font, error, (8.0, 8.0), color, (viewport.w, viewport.h))?;
+            self.text.end_frame();
+        }
+        Ok(())
+    }
+
+    /// Follows the cursor, given in pixels from the top left corner of a
+    /// viewport that is `height` pixels high.
+    pub fn cursor_moved(&mut self, x: f32, y: f32, height: f32) {
+        // Shadertoy counts from the bottom left like gl_FragCoord
+        self.cursor = Vector2::new(x, height - y);
+        if self.mouse_down {
+            self.mouse.x = self.cursor.x;
+            self.mouse.y = self.cursor.y;
+        }
+    }
+
+    pub fn mouse_pressed(&mut self) {
+        self.mouse_down = true;
+        self.mouse = Vector4::new(self.cursor.x, self.cursor.y, self.cursor.x, self.cursor.y);
+    }
+
+    pub fn mouse_released(&mut self) {
+        self.mouse_down = false;
+        self.mouse.z = -self.mouse.z.abs();
+        self.mouse.w = -self.mouse.w.abs();
+    }
+}
+
+fn duration_secs(duration: std::time::Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1e-9
+}
@@ -1,14 +1,13 @@
 
 use crate::render::shaders::Program;
 use crate::render::vertex::Vertex;
-use crate::render::array_buffer::ArrayBuffer;
-use crate::render::array_buffer::VertexArray;
+use crate::render::array_buffer::{self, ArrayBuffer, Topology, VertexArray};
 
 pub struct Scene {
     program_id: gl::types::GLuint,
-    vbo: ArrayBuffer,
+    /// Never read, but the vertex array draws from it for as long as it lives
+    _vbo: ArrayBuffer<Vertex>,
     vao: VertexArray,
-    gl: gl::Gl,
 }
 
 impl Scene {
@@ -23,28 +22,19 @@ impl Scene {
             Vertex { position: (0.0, 0.5, 0.0).into(), color: (0.0, 0.0, 1.0).into() }
         ];
 
-        let vao = VertexArray::new(gl);
-        vao.bind();
+        let mut vao = VertexArray::new(gl);
         let vbo = ArrayBuffer::new(gl);
 
         vbo.bind();
         vbo.upload_data(&vertices);
-        Vertex::vertex_attrib_pointers(gl);
         vbo.unbind();
-        vao.unbind();
+        vao.set_vertex_buffer(&vbo);
+        vao.set_topology(Topology::Triangles);
 
-        return Box::new(Scene { gl: gl.clone(), program_id: program.id(), vbo: vbo, vao: vao });
+        return Box::new(Scene { program_id: program.id(), _vbo: vbo, vao: vao });
     }
 
-    pub fn draw(&self) {
-        unsafe {
-            self.vao.bind();
-            self.gl.DrawArrays(
-                gl::TRIANGLES,
-                0,
-                3
-            );
-            self.vao.unbind();
-        }
+    pub fn draw(&self) -> Result<(), array_buffer::Error> {
+        self.vao.draw()
     }
 }
@@ -0,0 +1,79 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::render::shaders::Program;
+use crate::resources::Resources;
+
+/// Watches the shader files of loaded programs and reports which programs
+/// need to be relinked because one of their sources changed on disk.
+/// To pick up edits in a checkout rather than in the copy next to the
+/// executable, mount its assets with `Resources::mount_dev_dir`.
+pub struct ShaderWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    watched_dirs: HashSet<PathBuf>,
+    programs: HashMap<PathBuf, HashSet<gl::types::GLuint>>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Result<ShaderWatcher, notify::Error> {
+        let (tx, rx) = channel();
+        let watcher = notify::watcher(tx, Duration::from_millis(200))?;
+        Ok(ShaderWatcher {
+            watcher,
+            events: rx,
+            watched_dirs: HashSet::new(),
+            programs: HashMap::new(),
+        })
+    }
+
+    /// Starts watching every stage of `program` that is served from a file on
+    /// disk. Sources that only exist inside archives or memory are skipped.
+    pub fn watch(&mut self, resources: &Resources, program: &Program) -> Result<(), notify::Error> {
+        self.watch_resources(resources, program.id(), &program.resource_names())
+    }
+
+    /// Reports `program_id` as changed whenever one of `resource_names`
+    /// changes. Useful for programs that failed to build, which don't know
+    /// their sources yet.
+    pub fn watch_resources(&mut self, resources: &Resources, program_id: gl::types::GLuint, resource_names: &[String]) -> Result<(), notify::Error> {
+        for resource_name in resource_names {
+            let path = match resources.disk_path(resource_name) {
+                Some(path) => path,
+                None => continue,
+            };
+            // Editors tend to save by replacing the file, which drops a watch
+            // on the file itself, so watch the directory holding it instead.
+            if let Some(dir) = path.parent() {
+                if !self.watched_dirs.contains(dir) {
+                    self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                    self.watched_dirs.insert(dir.into());
+                }
+            }
+            self.programs.entry(path).or_default().insert(program_id);
+        }
+        Ok(())
+    }
+
+    /// Drains pending file system events and returns the ids of the programs
+    /// whose sources changed since the last call.
+    pub fn changed_programs(&self) -> Vec<gl::types::GLuint> {
+        let mut changed = HashSet::new();
+        while let Ok(event) = self.events.try_recv() {
+            let path = match event {
+                DebouncedEvent::Create(path) |
+                DebouncedEvent::Write(path) |
+                DebouncedEvent::Rename(_, path) => path,
+                _ => continue,
+            };
+            if let Some(program_ids) = self.programs.get(&path) {
+                changed.extend(program_ids.iter().cloned());
+            }
+        }
+        changed.into_iter().collect()
+    }
+}
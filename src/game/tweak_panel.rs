@@ -0,0 +1,463 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use failure::Fail;
+use nalgebra::{Vector2, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::Handle;
+use crate::render::font::Font;
+use crate::render::ring_buffer;
+use crate::render::shaders::{self, reflection, Program};
+use crate::render::shapes::{Rect, RectRenderer};
+use crate::render::text::TextRenderer;
+use crate::resources::{self, shader_stages, Resources};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "There is no program to save tweaks for")]
+    NoProgram,
+    #[fail(display = "Tweaks for {} can't be saved, {} is not below a mounted directory", program, name)]
+    NotWritable { program: String, name: String },
+    #[fail(display = "Failed to write tweaks to {:?}", path)]
+    Io { path: PathBuf, #[cause] inner: io::Error },
+    #[fail(display = "Failed to serialize tweaks for {}", program)]
+    Serialize { program: String, #[cause] inner: ron::ser::Error },
+    #[fail(display = "Failed to load tweaks {}", name)]
+    Load { name: String, #[cause] inner: resources::Error },
+    #[fail(display = "Failed to parse tweaks {}", name)]
+    Parse { name: String, #[cause] inner: ron::de::Error },
+}
+
+const PANEL_WIDTH: f32 = 300.0;
+const MARGIN: f32 = 8.0;
+const LABEL_WIDTH: f32 = 16.0;
+
+/// A `float` or float vector uniform listed by the panel.
+struct Tweak {
+    name: String,
+    value: Vec<f32>,
+    min: f32,
+    max: f32,
+    /// Shown with a swatch and `0..1` channels instead of open ranges.
+    color: bool,
+    /// Changed on the panel or loaded from the tweaks file. Only these are
+    /// set on the program, the others follow whatever the game sets.
+    touched: bool,
+    /// Still an active uniform of the program. Inactive tweaks are hidden but
+    /// kept, so saving doesn't drop what a shader edit commented out.
+    active: bool,
+}
+
+impl Tweak {
+    fn new(name: &str, value: Vec<f32>) -> Tweak {
+        let lower = name.to_lowercase();
+        let color = value.len() >= 3 && (lower.contains("color") || lower.contains("colour"));
+        let (min, max) = if color {
+            (0.0, 1.0)
+        } else {
+            default_range(&value)
+        };
+        Tweak {
+            name: name.into(),
+            value,
+            min,
+            max,
+            color,
+            touched: false,
+            active: true,
+        }
+    }
+}
+
+/// Sliders start out covering `0..1`, or twice the value for values outside
+/// of it.
+fn default_range(value: &[f32]) -> (f32, f32) {
+    value.iter().fold((0.0, 1.0), |(min, max), &component| {
+        (min.min(component * 2.0), max.max(component * 2.0))
+    })
+}
+
+/// How a tweak is stored in the tweaks file.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedTweak {
+    value: Vec<f32>,
+    min: f32,
+    max: f32,
+}
+
+impl SavedTweak {
+    /// A touched tweak with the saved value. A hand-edited file with an
+    /// empty or reversed range gets the default range instead, sliders
+    /// can't map anything onto that.
+    fn into_tweak(self, name: &str) -> Tweak {
+        let mut tweak = Tweak::new(name, self.value);
+        if self.min < self.max && (self.max - self.min).is_finite() {
+            tweak.min = self.min;
+            tweak.max = self.max;
+        }
+        tweak.touched = true;
+        tweak
+    }
+}
+
+/// One component of a tweak as laid out by the last draw.
+struct Slider {
+    tweak: String,
+    component: usize,
+    rect: Rect,
+}
+
+/// An overlay listing every active `float`, `vec2`, `vec3` and `vec4`
+/// uniform of a program with a slider per component. Uniforms with `color`
+/// or `colour` in their name get `0..1` channels and a swatch.
+///
+/// Values changed on the panel are set on the program every frame and can be
+/// saved to a RON file next to the shader, `shaders/plasma.frag` and
+/// `shaders/plasma` both save to `shaders/plasma.tweaks.ron`. The file is
+/// read back when the panel first sees the program, so tuned values stick
+/// around without changing the shader.
+pub struct TweakPanel {
+    rects: RectRenderer,
+    text: TextRenderer,
+    rect_program: Handle<Program>,
+    text_program: Handle<Program>,
+    visible: bool,
+    /// The name of the program the tweaks belong to.
+    program: Option<String>,
+    /// The `link_serial` of the program the values were last read from,
+    /// `None` when they have to be read again.
+    synced: Option<usize>,
+    tweaks: Vec<Tweak>,
+    panel: Option<Rect>,
+    sliders: Vec<Slider>,
+    dragging: Option<usize>,
+    cursor: Vector2<f32>,
+}
+
+impl TweakPanel {
+    /// `rect_program` and `text_program` are the `rect` and `font` shaders
+    /// from the assets.
+    pub fn new(gl: &gl::Gl, rect_program: Handle<Program>, text_program: Handle<Program>) -> Result<TweakPanel, ring_buffer::Error> {
+        Ok(TweakPanel {
+            rects: RectRenderer::new(gl)?,
+            text: TextRenderer::new(gl)?,
+            rect_program,
+            text_program,
+            visible: false,
+            program: None,
+            synced: None,
+            tweaks: vec!(),
+            panel: None,
+            sliders: vec!(),
+            dragging: None,
+            cursor: Vector2::zeros(),
+        })
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.dragging = None;
+        // The game may have changed untouched values while it was hidden
+        self.synced = None;
+        if !self.visible {
+            self.panel = None;
+            self.sliders.clear();
+        }
+    }
+
+    /// Sets the tweaked uniforms of `program`. Meant to be called every frame,
+    /// whether the panel is shown or not. Uniform values are only read back,
+    /// which stalls on the GPU, when the program was relinked or the panel
+    /// was just shown. Switching to another program starts over with that
+    /// program's tweaks file.
+    pub fn sync(&mut self, program: &Program, resources: &Resources) -> Result<(), Error> {
+        let name = program.name().unwrap_or("");
+        let mut loaded = Ok(());
+        if self.program.as_deref() != Some(name) {
+            self.program = Some(name.into());
+            self.synced = None;
+            self.tweaks.clear();
+            self.sliders.clear();
+            self.dragging = None;
+            loaded = self.load(resources, name);
+        }
+        if self.synced != Some(program.link_serial()) {
+            self.synced = Some(program.link_serial());
+            self.read_uniforms(program);
+        }
+        for tweak in self.tweaks.iter().filter(|tweak| tweak.touched && tweak.active) {
+            // The type was checked against the reflection when reading
+            let _ = set_floats(program, &tweak.name, &tweak.value);
+        }
+        loaded
+    }
+
+    /// Catches up with the active uniforms of `program` and their values.
+    fn read_uniforms(&mut self, program: &Program) {
+        let mut previous = std::mem::take(&mut self.tweaks);
+        for uniform in program.reflection().uniforms() {
+            let components = match reflection::float_components(uniform.kind) {
+                Some(components) if uniform.size == 1 => components,
+                _ => continue,
+            };
+            let current = program.float_uniform(&uniform.name).unwrap_or_else(|_| vec![0.0; components]);
+            let existing = previous.iter()
+                .position(|tweak| tweak.name == uniform.name && tweak.value.len() == components)
+                .map(|index| previous.remove(index));
+            let tweak = match existing {
+                Some(mut tweak) => {
+                    if !tweak.touched {
+                        tweak.value = current;
+                    }
+                    tweak.active = true;
+                    tweak
+                },
+                None => Tweak::new(&uniform.name, current),
+            };
+            self.tweaks.push(tweak);
+        }
+        for mut tweak in previous.into_iter().filter(|tweak| tweak.touched) {
+            tweak.active = false;
+            self.tweaks.push(tweak);
+        }
+    }
+
+    fn load(&mut self, resources: &Resources, program: &str) -> Result<(), Error> {
+        let name = tweaks_resource_name(program);
+        if !resources.exists(&name) {
+            return Ok(());
+        }
+        let data = resources.load_bytes(&name).map_err(|inner| Error::Load {
+            name: name.clone(),
+            inner,
+        })?;
+        let text = String::from_utf8_lossy(&data);
+        let saved: BTreeMap<String, SavedTweak> = ron::de::from_str(&text).map_err(|inner| Error::Parse {
+            name: name.clone(),
+            inner,
+        })?;
+        for (uniform, saved) in saved {
+            let mut tweak = saved.into_tweak(&uniform);
+            tweak.active = false;
+            self.tweaks.push(tweak);
+        }
+        Ok(())
+    }
+
+    /// Writes every tweaked value to the tweaks file of the current program
+    /// and returns where it went. That is below the last mounted directory,
+    /// the one given to `Resources::mount_dev_dir` if there is one.
+    pub fn save(&self, resources: &Resources) -> Result<PathBuf, Error> {
+        let program = self.program.as_ref().ok_or(Error::NoProgram)?;
+        let name = tweaks_resource_name(program);
+        let path = resources.writable_path(&name).ok_or_else(|| Error::NotWritable {
+            program: program.clone(),
+            name: name.clone(),
+        })?;
+        let saved = self.tweaks.iter()
+            .filter(|tweak| tweak.touched)
+            .map(|tweak| (tweak.name.clone(), SavedTweak {
+                value: tweak.value.clone(),
+                min: tweak.min,
+                max: tweak.max,
+            }))
+            .collect::<BTreeMap<_, _>>();
+        let text = ron::ser::to_string_pretty(&saved, Default::default()).map_err(|inner| Error::Serialize {
+            program: program.clone(),
+            inner,
+        })?;
+        fs::write(&path, text).map_err(|inner| Error::Io {
+            path: path.clone(),
+            inner,
+        })?;
+        Ok(path)
+    }
+
+    /// Draws the panel along the right edge of a window that is
+    /// `screen_size` pixels large, if it is shown.
+    pub fn draw(&mut self, font: &Font, screen_size: (i32, i32)) -> Result<(), failure::Error> {
+        if !self.visible {
+            return Ok(());
+        }
+        let line_height = font.line_height().ceil();
+        let left = screen_size.0 as f32 - PANEL_WIDTH - MARGIN;
+        let inner_left = left + MARGIN;
+        let inner_width = PANEL_WIDTH - 2.0 * MARGIN;
+        let white = Vector4::new(1.0, 1.0, 1.0, 1.0);
+        let dim = Vector4::new(0.7, 0.7, 0.75, 1.0);
+
+        let mut rects = vec!();
+        let mut texts = vec!();
+        let mut sliders = vec!();
+        let mut y = MARGIN * 2.0;
+        let title = match self.program {
+            Some(ref program) => format!("{}  (F2 saves)", program),
+            None => "no program".into(),
+        };
+        texts.push((title, (inner_left, y), dim));
+        y += line_height + MARGIN;
+
+        for tweak in self.tweaks.iter().filter(|tweak| tweak.active) {
+            let label = if tweak.touched { format!("{} *", tweak.name) } else { tweak.name.clone() };
+            texts.push((label, (inner_left, y), white));
+            if tweak.color {
+                let alpha = tweak.value.get(3).cloned().unwrap_or(1.0);
+                let swatch = Vector4::new(tweak.value[0], tweak.value[1], tweak.value[2], alpha);
+                rects.push(Rect::new(inner_left + inner_width - 2.0 * line_height, y + 2.0, 2.0 * line_height, line_height - 4.0, swatch));
+            }
+            y += line_height;
+            let names = if tweak.color { ["r", "g", "b", "a"] } else { ["x", "y", "z", "w"] };
+            for (component, &value) in tweak.value.iter().enumerate() {
+                let bar = Rect::new(inner_left + LABEL_WIDTH, y + 2.0, inner_width - LABEL_WIDTH, line_height - 4.0, Vector4::new(0.2, 0.2, 0.25, 1.0));
+                let t = ((value - tweak.min) / (tweak.max - tweak.min)).clamp(0.0, 1.0);
+                let fill = Vector4::new(0.35, 0.45, 0.8, 1.0);
+                rects.push(bar);
+                rects.push(Rect::new(bar.x, bar.y, bar.width * t, bar.height, fill));
+                if tweak.value.len() > 1 {
+                    texts.push((names[component].to_string(), (inner_left, y), dim));
+                }
+                texts.push((format!("{:.3}", value), (bar.x + 4.0, y), white));
+                sliders.push(Slider { tweak: tweak.name.clone(), component, rect: bar });
+                y += line_height;
+            }
+            y += MARGIN / 2.0;
+        }
+
+        let panel = Rect::new(left, MARGIN, PANEL_WIDTH, y, Vector4::new(0.05, 0.05, 0.08, 0.85));
+        rects.insert(0, panel);
+        self.panel = Some(panel);
+        self.sliders = sliders;
+
+        self.rects.draw(&self.rect_program, &rects, screen_size)?;
+        for (text, position, color) in texts {
+            self.text.draw(&self.text_program, font, &text, position, color, screen_size)?;
+        }
+        self.rects.end_frame();
+        self.text.end_frame();
+        Ok(())
+    }
+
+    /// Returns whether the panel took the click, in which case it shouldn't
+    /// reach anything under it.
+    pub fn mouse_pressed(&mut self) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        self.dragging = self.sliders.iter().position(|slider| slider.rect.contains(x, y));
+        self.drag();
+        self.dragging.is_some() || self.panel.map_or(false, |panel| panel.contains(x, y))
+    }
+
+    /// Follows the cursor, in pixels from the top left corner of the window.
+    /// Returns whether a slider is being dragged.
+    pub fn cursor_moved(&mut self, x: f32, y: f32) -> bool {
+        self.cursor = Vector2::new(x, y);
+        self.drag();
+        self.dragging.is_some()
+    }
+
+    pub fn mouse_released(&mut self) -> bool {
+        self.dragging.take().is_some()
+    }
+
+    fn drag(&mut self) {
+        let sliders = &self.sliders;
+        let slider = match self.dragging.and_then(|index| sliders.get(index)) {
+            Some(slider) => slider,
+            None => return,
+        };
+        let t = ((self.cursor.x - slider.rect.x) / slider.rect.width).clamp(0.0, 1.0);
+        // The uniforms may have changed since the sliders were laid out
+        let tweak = match self.tweaks.iter_mut().find(|tweak| tweak.name == slider.tweak && tweak.active) {
+            Some(tweak) => tweak,
+            None => return,
+        };
+        if let Some(value) = tweak.value.get_mut(slider.component) {
+            *value = tweak.min + t * (tweak.max - tweak.min);
+            tweak.touched = true;
+        }
+    }
+}
+
+fn set_floats(program: &Program, name: &str, value: &[f32]) -> Result<(), shaders::Error> {
+    match *value {
+        [x] => program.set_uniform_if_active(name, &x),
+        [x, y] => program.set_uniform_if_active(name, &Vector2::new(x, y)),
+        [x, y, z] => program.set_uniform_if_active(name, &Vector3::new(x, y, z)),
+        [x, y, z, w] => program.set_uniform_if_active(name, &Vector4::new(x, y, z, w)),
+        _ => Ok(()),
+    }
+}
+
+/// `shaders/plasma.frag` and `shaders/plasma` both become
+/// `shaders/plasma.tweaks.ron`.
+fn tweaks_resource_name(program: &str) -> String {
+    let base = match shader_stages::extension(program) {
+        Some(extension) => &program[..program.len() - extension.len()],
+        None => program,
+    };
+    format!("{}.tweaks.ron", base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tweaks_files_are_named_after_the_program() {
+        for &(program, expected) in &[
+            ("shaders/plasma", "shaders/plasma.tweaks.ron"),
+            ("shaders/plasma.frag", "shaders/plasma.tweaks.ron"),
+            ("shaders/plasma.comp", "shaders/plasma.tweaks.ron"),
+            ("shaders/common.glsl", "shaders/common.glsl.tweaks.ron"),
+        ] {
+            assert_eq!(tweaks_resource_name(program), expected);
+        }
+    }
+
+    #[test]
+    fn default_ranges_cover_twice_the_value() {
+        assert_eq!(default_range(&[0.5]), (0.0, 1.0));
+        assert_eq!(default_range(&[3.0]), (0.0, 6.0));
+        assert_eq!(default_range(&[-2.0, 0.25]), (-4.0, 1.0));
+    }
+
+    #[test]
+    fn colors_are_told_apart_by_name_and_size() {
+        let color = Tweak::new("baseColour", vec![2.0, 0.5, 0.5]);
+        assert!(color.color);
+        assert_eq!((color.min, color.max), (0.0, 1.0));
+        assert!(Tweak::new("u_Color", vec![0.0; 4]).color);
+        assert!(!Tweak::new("color", vec![2.0]).color);
+        assert!(!Tweak::new("tint", vec![0.0; 3]).color);
+    }
+
+    #[test]
+    fn saved_tweaks_round_trip() {
+        let mut saved = BTreeMap::new();
+        saved.insert("speed".to_owned(), SavedTweak { value: vec![1.5], min: -1.0, max: 4.0 });
+        let text = ron::ser::to_string_pretty(&saved, Default::default()).unwrap();
+        let loaded: BTreeMap<String, SavedTweak> = ron::de::from_str(&text).unwrap();
+        let tweak = loaded.into_iter().next().map(|(name, saved)| saved.into_tweak(&name)).unwrap();
+        assert_eq!(tweak.name, "speed");
+        assert_eq!(tweak.value, [1.5]);
+        assert_eq!((tweak.min, tweak.max), (-1.0, 4.0));
+        assert!(tweak.touched);
+    }
+
+    #[test]
+    fn empty_saved_ranges_get_the_default_range() {
+        for &(min, max) in &[(2.0, 2.0), (3.0, 1.0), (0.0, f32::INFINITY)] {
+            let tweak = SavedTweak { value: vec![2.0], min, max }.into_tweak("speed");
+            assert_eq!((tweak.min, tweak.max), (0.0, 4.0));
+        }
+    }
+}
@@ -0,0 +1,8 @@
+extern crate rustgl_render_derive;
+// Lets derived code refer to this crate as `::rustgl` from inside it too.
+extern crate self as rustgl;
+
+pub mod resources;
+pub mod assets;
+pub mod game;
+pub mod render;
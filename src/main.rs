@@ -1,19 +1,15 @@
-extern crate rustgl_render_derive;
-
-pub mod resources;
-pub mod game;
-pub mod render;
-
 use std::path::Path;
+use std::sync::Arc;
 use std::ffi::{CStr};
 use glutin::GlContext;
 use slog::Drain;
 use slog::info;
 use slog::debug;
+use slog::warn;
 use slog::o;
 
-use resources::Resources;
-use game::Game;
+use rustgl::resources::Resources;
+use rustgl::game::Game;
 
 fn main() {
     let log_decorator = slog_term::TermDecorator::new().build();
@@ -35,9 +31,24 @@ fn main() {
 
     let version = String::from_utf8(data).unwrap();
     info!(log, "OpenGL Version {}", version);
-    let resources = Resources::from_relative_exe(Path::new("assets")).unwrap();
-    debug!(log, "Resource path: {:?}", resources.root());
-    let mut game = Game::new(&gl, &resources, &gl_window, &mut event_loop, &log).unwrap();
+    let mut resources = Resources::from_relative_exe(Path::new("assets")).unwrap();
+    if let Some(dir) = arg_value("--assets-dir") {
+        resources.mount_dev_dir(Path::new(&dir));
+    }
+    if let Some(dir) = arg_value("--cache-dir") {
+        if let Err(e) = resources.set_cache_dir(Some(dir.into())) {
+            warn!(log, "Program binaries won't be cached: {}", failure_to_string(e.into()));
+        }
+    }
+    let resources = Arc::new(resources);
+    debug!(log, "Resources: {:?}", resources);
+    let mut game = Game::new(&gl, resources.clone(), &gl_window, &mut event_loop, &log).unwrap();
+    if let Some(shader) = arg_value("--playground") {
+        if let Err(e) = game.open_playground(&shader) {
+            println!("{}", failure_to_string(e));
+            std::process::exit(1);
+        }
+    }
     if let Err(e) = game.run() {
         println!("{}", failure_to_string(e));
     }
@@ -45,6 +56,21 @@ fn main() {
     info!(log, "Okay then, goodbye.");
 }
 
+/// The value following `flag` on the command line. The game understands
+/// `--playground <file>`, where `file` is a fragment shader in
+/// `assets/shaders`, `--assets-dir <dir>`, a directory of assets to edit
+/// while the game runs, see `Resources::mount_dev_dir`, and
+/// `--cache-dir <dir>`, where compiled program binaries are kept.
+fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+    }
+    None
+}
+
 pub fn failure_to_string(e: failure::Error) -> String {
     use std::fmt::Write;
 
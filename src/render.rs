@@ -2,6 +2,12 @@ pub mod shaders;
 pub mod types;
 pub mod vertex;
 pub mod array_buffer;
+pub mod ring_buffer;
+pub mod binding_points;
 pub mod viewport;
 pub mod color_buffer;
-pub mod font;
\ No newline at end of file
+pub mod font;
+pub mod texture;
+pub mod text;
+pub mod shapes;
+pub mod uniform_block;
\ No newline at end of file
@@ -1,5 +1,29 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use failure::Fail;
 use gl;
 
+use super::ring_buffer::RingBuffer;
+use super::uniform_block::UniformBlock;
+use super::vertex::VertexLayout;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Vertex array {} has no vertex buffer or vertex count", vao)]
+    NoVertices { vao: gl::types::GLuint },
+    #[fail(display = "Vertex array {} has no primitive topology", vao)]
+    NoTopology { vao: gl::types::GLuint },
+    #[fail(display = "Can't draw {} elements from {} of vertex array {}, which has {}", count, first, vao, len)]
+    OutOfRange { vao: gl::types::GLuint, first: usize, count: usize, len: usize },
+    #[fail(display = "Can't draw {} instances of vertex array {}, its instance buffer has attributes for {}", instances, vao, max)]
+    TooManyInstances { vao: gl::types::GLuint, instances: usize, max: usize },
+    #[fail(display = "{} has fields without a divisor and can't be an instance buffer", layout)]
+    NotAnInstanceLayout { layout: &'static str },
+    #[fail(display = "Vertex array {} needs {} indirect commands", vao, kind)]
+    IndirectCommandMismatch { vao: gl::types::GLuint, kind: &'static str },
+}
+
 
 pub trait BufferType {
     const BUFFER_TYPE: gl::types::GLuint;
@@ -15,27 +39,118 @@ impl BufferType for BufferTypeElementArray {
     const BUFFER_TYPE: gl::types::GLuint = gl::ELEMENT_ARRAY_BUFFER;
 }
 
-pub struct Buffer<B>
-where B: BufferType {
+pub struct BufferTypeUniform;
+impl BufferType for BufferTypeUniform {
+    const BUFFER_TYPE: gl::types::GLuint = gl::UNIFORM_BUFFER;
+}
+
+pub struct BufferTypeShaderStorage;
+impl BufferType for BufferTypeShaderStorage {
+    const BUFFER_TYPE: gl::types::GLuint = gl::SHADER_STORAGE_BUFFER;
+}
+
+pub struct BufferTypeDrawIndirect;
+impl BufferType for BufferTypeDrawIndirect {
+    const BUFFER_TYPE: gl::types::GLuint = gl::DRAW_INDIRECT_BUFFER;
+}
+
+pub struct BufferTypePixelUnpack;
+impl BufferType for BufferTypePixelUnpack {
+    const BUFFER_TYPE: gl::types::GLuint = gl::PIXEL_UNPACK_BUFFER;
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::BufferTypeUniform {}
+    impl Sealed for super::BufferTypeShaderStorage {}
+}
+
+/// A target with numbered binding points that shaders refer to, see
+/// `BindingPoints`. Sealed, as `BindingPoints` keeps a table for each of
+/// these targets.
+pub trait IndexedBufferType: BufferType + sealed::Sealed {
+    /// Which of the tables of `BindingPoints` tracks this target.
+    const TABLE: usize;
+    /// For error messages, e.g. `uniform`.
+    const NAME: &'static str;
+    /// The limit on binding points, e.g. `GL_MAX_UNIFORM_BUFFER_BINDINGS`.
+    const MAX_BINDINGS: gl::types::GLenum;
+    /// The alignment `bind_range` offsets need, e.g.
+    /// `GL_UNIFORM_BUFFER_OFFSET_ALIGNMENT`.
+    const OFFSET_ALIGNMENT: gl::types::GLenum;
+}
+
+impl IndexedBufferType for BufferTypeUniform {
+    const TABLE: usize = 0;
+    const NAME: &'static str = "uniform";
+    const MAX_BINDINGS: gl::types::GLenum = gl::MAX_UNIFORM_BUFFER_BINDINGS;
+    const OFFSET_ALIGNMENT: gl::types::GLenum = gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT;
+}
+
+impl IndexedBufferType for BufferTypeShaderStorage {
+    const TABLE: usize = 1;
+    const NAME: &'static str = "shader storage";
+    const MAX_BINDINGS: gl::types::GLenum = gl::MAX_SHADER_STORAGE_BUFFER_BINDINGS;
+    const OFFSET_ALIGNMENT: gl::types::GLenum = gl::SHADER_STORAGE_BUFFER_OFFSET_ALIGNMENT;
+}
+
+/// How often the contents of a buffer are expected to change, which the
+/// driver uses to decide where to keep it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Uploaded once and drawn many times.
+    Static,
+    /// Changed now and then and drawn many times in between.
+    Dynamic,
+    /// Rewritten for about every draw, e.g. text and debug lines.
+    Stream,
+}
+
+impl BufferUsage {
+    pub fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// A buffer of elements of type `T`, which keeps track of how many were
+/// uploaded so vertex arrays drawing from it know their size.
+pub struct Buffer<B, T>
+where B: BufferType, T: Copy {
     vbo_id: gl::types::GLuint,
     gl: gl::Gl,
-    _marker: ::std::marker::PhantomData<B>,
+    /// The size of the current data store in elements.
+    capacity: Cell<usize>,
+    /// The number of elements uploaded, shared with the vertex arrays the
+    /// buffer is attached to.
+    len: Rc<Cell<usize>>,
+    _marker: ::std::marker::PhantomData<(B, T)>,
 }
 
-impl<B> Buffer<B>
-where B: BufferType {
-    pub fn new(gl: &gl::Gl) -> ArrayBuffer {
+impl<B, T> Buffer<B, T>
+where B: BufferType, T: Copy {
+    pub fn new(gl: &gl::Gl) -> Buffer<B, T> {
         let mut vbo_id: gl::types::GLuint = 0;
         unsafe {
             gl.GenBuffers(1, &mut vbo_id);
         }
-        ArrayBuffer {
+        Buffer {
             vbo_id,
             gl: gl.clone(),
+            capacity: Cell::new(0),
+            len: Rc::new(Cell::new(0)),
             _marker: ::std::marker::PhantomData,
         }
     }
 
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vbo_id
+    }
+
     pub fn bind(&self) {
         unsafe {
             self.gl.BindBuffer(B::BUFFER_TYPE, self.vbo_id);
@@ -48,20 +163,84 @@ where B: BufferType {
         }
     }
 
-    pub fn upload_data<T>(&self, data: &[T]) {
+    /// The number of elements uploaded.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len.get() == 0
+    }
+
+    /// The number of elements the data store has room for.
+    pub fn capacity(&self) -> usize {
+        self.capacity.get()
+    }
+
+    /// Replaces the data store with `data`, for data that won't change.
+    pub fn upload_data(&self, data: &[T]) {
+        self.upload_data_with_usage(data, BufferUsage::Static);
+    }
+
+    /// Replaces the data store with `data`. The buffer has to be bound.
+    pub fn upload_data_with_usage(&self, data: &[T], usage: BufferUsage) {
         unsafe {
             self.gl.BufferData(
-                gl::ARRAY_BUFFER,
+                B::BUFFER_TYPE,
                 (data.len() * std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
                 data.as_ptr() as *const gl::types::GLvoid,
-                gl::STATIC_DRAW
+                usage.gl_enum()
+            );
+        }
+        self.capacity.set(data.len());
+        self.len.set(data.len());
+    }
+
+    /// Overwrites part of the data store, starting `offset` elements in,
+    /// without reallocating it. Elements written past the end of the
+    /// uploaded ones count as uploaded. The buffer has to be bound.
+    pub fn update_data(&self, offset: usize, data: &[T]) {
+        assert!(
+            offset + data.len() <= self.capacity.get(),
+            "update of {} elements at {} overflows a buffer of {}", data.len(), offset, self.capacity.get()
+        );
+        let size = std::mem::size_of::<T>();
+        unsafe {
+            self.gl.BufferSubData(
+                B::BUFFER_TYPE,
+                (offset * size) as gl::types::GLintptr,
+                (data.len() * size) as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid
+            );
+        }
+        self.len.set(self.len.get().max(offset + data.len()));
+    }
+
+    /// Replaces the contents for data rewritten every frame. If it fits, the
+    /// old data store is orphaned instead of reallocated: the driver hands
+    /// out fresh memory of the same size while draws still in flight keep
+    /// reading the old one, so there is no waiting for the GPU. The buffer
+    /// has to be bound.
+    pub fn stream_data(&self, data: &[T], usage: BufferUsage) {
+        if data.is_empty() || data.len() > self.capacity.get() {
+            self.upload_data_with_usage(data, usage);
+            return;
+        }
+        unsafe {
+            self.gl.BufferData(
+                B::BUFFER_TYPE,
+                (self.capacity.get() * std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                std::ptr::null(),
+                usage.gl_enum()
             );
         }
+        self.len.set(0);
+        self.update_data(0, data);
     }
 }
 
-impl<B> Drop for Buffer<B>
-where B: BufferType {
+impl<B, T> Drop for Buffer<B, T>
+where B: BufferType, T: Copy {
     fn drop(&mut self) {
         unsafe {
             self.gl.DeleteBuffers(1, &mut self.vbo_id);
@@ -69,12 +248,177 @@ where B: BufferType {
     }
 }
 
-pub type ArrayBuffer = Buffer<BufferTypeArray>;
-pub type ElementArrayBuffer = Buffer<BufferTypeElementArray>;
+pub type ArrayBuffer<T> = Buffer<BufferTypeArray, T>;
+pub type ElementArrayBuffer<T> = Buffer<BufferTypeElementArray, T>;
+pub type ShaderStorageBuffer<T> = Buffer<BufferTypeShaderStorage, T>;
+pub type DrawIndirectBuffer<T> = Buffer<BufferTypeDrawIndirect, T>;
+pub type PixelUnpackBuffer<T> = Buffer<BufferTypePixelUnpack, T>;
+
+/// A command read by an indirect draw, which a compute shader can write to
+/// decide what gets drawn without a round trip to the CPU.
+pub trait IndirectCommand: Copy {
+    /// Whether the command is for `glDrawElementsIndirect`.
+    const INDEXED: bool;
+}
+
+/// The layout `glDrawArraysIndirect` reads.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct DrawArraysIndirectCommand {
+    pub count: u32,
+    pub instance_count: u32,
+    pub first: u32,
+    pub base_instance: u32,
+}
+
+impl IndirectCommand for DrawArraysIndirectCommand {
+    const INDEXED: bool = false;
+}
+
+/// The layout `glDrawElementsIndirect` reads.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct DrawElementsIndirectCommand {
+    pub count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub base_instance: u32,
+}
+
+impl IndirectCommand for DrawElementsIndirectCommand {
+    const INDEXED: bool = true;
+}
+
+/// The types `glDrawElements` accepts as indices.
+pub trait IndexType: Copy {
+    const GL_TYPE: gl::types::GLenum;
+}
+
+impl IndexType for u8 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl IndexType for u16 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_SHORT;
+}
+
+impl IndexType for u32 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_INT;
+}
+
+/// An element array buffer holding indices of type `I`.
+pub struct IndexBuffer<I>
+where I: IndexType {
+    buffer: ElementArrayBuffer<I>,
+    usage: BufferUsage,
+}
+
+impl<I> IndexBuffer<I>
+where I: IndexType {
+    pub fn new(gl: &gl::Gl, indices: &[I]) -> IndexBuffer<I> {
+        IndexBuffer::with_usage(gl, indices, BufferUsage::Static)
+    }
+
+    /// An index buffer that expects `upload` to be called as often as
+    /// `usage` says.
+    pub fn with_usage(gl: &gl::Gl, indices: &[I], usage: BufferUsage) -> IndexBuffer<I> {
+        let buffer = IndexBuffer {
+            buffer: ElementArrayBuffer::new(gl),
+            usage,
+        };
+        buffer.upload(indices);
+        buffer
+    }
+
+    /// Replaces the indices. The element array binding is part of the vertex
+    /// array state, so this leaves whatever vertex array is bound alone.
+    pub fn upload(&self, indices: &[I]) {
+        unsafe {
+            let gl = &self.buffer.gl;
+            let mut bound: gl::types::GLint = 0;
+            gl.GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut bound);
+            gl.BindVertexArray(0);
+            self.buffer.bind();
+            if self.usage == BufferUsage::Static {
+                self.buffer.upload_data(indices);
+            } else {
+                self.buffer.stream_data(indices, self.usage);
+            }
+            self.buffer.unbind();
+            gl.BindVertexArray(bound as gl::types::GLuint);
+        }
+    }
 
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+/// The kind of primitive a vertex array's vertices make up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Topology {
+    Points,
+    Lines,
+    LineStrip,
+    LineLoop,
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl Topology {
+    pub fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            Topology::Points => gl::POINTS,
+            Topology::Lines => gl::LINES,
+            Topology::LineStrip => gl::LINE_STRIP,
+            Topology::LineLoop => gl::LINE_LOOP,
+            Topology::Triangles => gl::TRIANGLES,
+            Topology::TriangleStrip => gl::TRIANGLE_STRIP,
+            Topology::TriangleFan => gl::TRIANGLE_FAN,
+        }
+    }
+}
+
+/// Where the vertices of a vertex array come from.
+#[derive(Clone, Debug)]
+enum Vertices {
+    /// A buffer of the named vertex type, with its live element count.
+    Buffer { layout: &'static str, len: Rc<Cell<usize>> },
+    /// No attributes, the shader makes up the vertices from `gl_VertexID`.
+    Generated { count: usize },
+}
+
+/// The index buffer set on a vertex array.
+#[derive(Clone, Debug)]
+struct Indices {
+    kind: gl::types::GLenum,
+    size: usize,
+    len: Rc<Cell<usize>>,
+}
+
+/// The instance buffer set on a vertex array.
+#[derive(Clone, Debug)]
+struct Instances {
+    divisor: usize,
+    len: Rc<Cell<usize>>,
+}
+
+/// A vertex array object that remembers how it was set up: the vertex type
+/// of its buffer, the index buffer if there is one and the kind of primitive
+/// to draw, so `draw` needs no arguments.
 pub struct VertexArray {
     gl: gl::Gl,
     vao_id: gl::types::GLuint,
+    vertices: Option<Vertices>,
+    indices: Option<Indices>,
+    instances: Option<Instances>,
+    topology: Option<Topology>,
 }
 
 impl VertexArray {
@@ -87,9 +431,17 @@ impl VertexArray {
         VertexArray {
             gl: gl.clone(),
             vao_id,
+            vertices: None,
+            indices: None,
+            instances: None,
+            topology: None,
         }
     }
 
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vao_id
+    }
+
     pub fn bind(&self) {
         unsafe {
             self.gl.BindVertexArray(self.vao_id);
@@ -101,6 +453,232 @@ impl VertexArray {
             self.gl.BindVertexArray(0);
         }
     }
+
+    /// Reads vertices of type `V` from `buffer`, with the attribute layout
+    /// the vertex type was derived with.
+    pub fn set_vertex_buffer<V: VertexLayout>(&mut self, buffer: &ArrayBuffer<V>) {
+        self.bind();
+        buffer.bind();
+        V::vertex_attrib_pointers(&self.gl);
+        buffer.unbind();
+        self.unbind();
+        self.vertices = Some(Vertices::Buffer {
+            layout: std::any::type_name::<V>(),
+            len: buffer.len.clone(),
+        });
+    }
+
+    /// Reads vertices of type `V` from `ring`. Draw the data of a frame with
+    /// `draw_range`, passing the index `RingBuffer::write` returned as
+    /// `first`.
+    pub fn set_ring_buffer<V: VertexLayout>(&mut self, ring: &RingBuffer<BufferTypeArray, V>) {
+        self.bind();
+        ring.bind();
+        V::vertex_attrib_pointers(&self.gl);
+        ring.unbind();
+        self.unbind();
+        self.vertices = Some(Vertices::Buffer {
+            layout: std::any::type_name::<V>(),
+            len: Rc::new(Cell::new(ring.capacity() * ring.sections())),
+        });
+    }
+
+    /// Reads per-instance attributes of type `V` from `buffer`. Every field
+    /// of `V` needs a `#[divisor = ?]` next to its location, otherwise it
+    /// would advance per vertex like those of the vertex buffer and
+    /// `Error::NotAnInstanceLayout` is returned.
+    ///
+    /// ```ignore
+    /// #[derive(VertexAttribPointers, Copy, Clone)]
+    /// #[repr(C, packed)]
+    /// struct Instance {
+    ///     #[location = 2]
+    ///     #[divisor = 1]
+    ///     offset: f32_f32_f32,
+    /// }
+    ///
+    /// vao.set_instance_buffer(&offsets)?;
+    /// vao.draw_instances()?;
+    /// ```
+    pub fn set_instance_buffer<V: VertexLayout>(&mut self, buffer: &ArrayBuffer<V>) -> Result<(), Error> {
+        if V::DIVISOR == 0 {
+            return Err(Error::NotAnInstanceLayout { layout: std::any::type_name::<V>() });
+        }
+        self.bind();
+        buffer.bind();
+        V::vertex_attrib_pointers(&self.gl);
+        buffer.unbind();
+        self.unbind();
+        self.instances = Some(Instances { divisor: V::DIVISOR, len: buffer.len.clone() });
+        Ok(())
+    }
+
+    /// The number of instances the buffer set with `set_instance_buffer` has
+    /// attributes for, its length times its divisor.
+    pub fn instance_count(&self) -> usize {
+        self.instances.as_ref().map_or(0, |instances| instances.len.get() * instances.divisor)
+    }
+
+    /// Draws `count` vertices without any attributes, for shaders that make
+    /// their vertices up from `gl_VertexID`.
+    pub fn set_vertex_count(&mut self, count: usize) {
+        self.vertices = Some(Vertices::Generated { count });
+    }
+
+    /// Makes `indices` the element array of this vertex array, so `draw`
+    /// goes through them.
+    pub fn set_index_buffer<I: IndexType>(&mut self, indices: &IndexBuffer<I>) {
+        self.bind();
+        indices.buffer.bind();
+        // Unbinding the element array while the vertex array is still bound
+        // would detach it again
+        self.unbind();
+        indices.buffer.unbind();
+        self.indices = Some(Indices {
+            kind: I::GL_TYPE,
+            size: std::mem::size_of::<I>(),
+            len: indices.buffer.len.clone(),
+        });
+    }
+
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = Some(topology);
+    }
+
+    pub fn topology(&self) -> Option<Topology> {
+        self.topology
+    }
+
+    /// The name of the vertex type set with `set_vertex_buffer`.
+    pub fn vertex_layout(&self) -> Option<&'static str> {
+        match self.vertices {
+            Some(Vertices::Buffer { layout, .. }) => Some(layout),
+            _ => None,
+        }
+    }
+
+    /// The number of vertices, or indices if there is an index buffer, that
+    /// `draw` draws.
+    pub fn len(&self) -> usize {
+        match (&self.indices, &self.vertices) {
+            (Some(indices), _) => indices.len.get(),
+            (None, Some(Vertices::Buffer { len, .. })) => len.get(),
+            (None, Some(Vertices::Generated { count })) => *count,
+            (None, None) => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Draws everything in the buffers as the primitive set with
+    /// `set_topology`.
+    pub fn draw(&self) -> Result<(), Error> {
+        self.draw_range(0, self.len())
+    }
+
+    /// Draws `count` vertices starting at `first`, or `count` indices
+    /// starting at index `first` if there is an index buffer.
+    pub fn draw_range(&self, first: usize, count: usize) -> Result<(), Error> {
+        let mode = self.draw_mode(first, count)?;
+        self.bind();
+        unsafe {
+            match self.indices {
+                Some(ref indices) => self.gl.DrawElements(
+                    mode,
+                    count as gl::types::GLsizei,
+                    indices.kind,
+                    (first * indices.size) as *const gl::types::GLvoid
+                ),
+                None => self.gl.DrawArrays(mode, first as gl::types::GLint, count as gl::types::GLsizei),
+            }
+        }
+        self.unbind();
+        Ok(())
+    }
+
+    /// Draws every instance the instance buffer has attributes for.
+    pub fn draw_instances(&self) -> Result<(), Error> {
+        self.draw_range_instanced(0, self.len(), self.instance_count())
+    }
+
+    /// Draws everything `instances` times in a single call, with
+    /// `gl_InstanceID` counting the copies. With an instance buffer set,
+    /// `instances` can't be more than `instance_count`.
+    pub fn draw_instanced(&self, instances: usize) -> Result<(), Error> {
+        self.draw_range_instanced(0, self.len(), instances)
+    }
+
+    /// Like `draw_range`, `instances` times.
+    pub fn draw_range_instanced(&self, first: usize, count: usize, instances: usize) -> Result<(), Error> {
+        let mode = self.draw_mode(first, count)?;
+        if self.instances.is_some() && instances > self.instance_count() {
+            return Err(Error::TooManyInstances { vao: self.vao_id, instances, max: self.instance_count() });
+        }
+        self.bind();
+        unsafe {
+            match self.indices {
+                Some(ref indices) => self.gl.DrawElementsInstanced(
+                    mode,
+                    count as gl::types::GLsizei,
+                    indices.kind,
+                    (first * indices.size) as *const gl::types::GLvoid,
+                    instances as gl::types::GLsizei
+                ),
+                None => self.gl.DrawArraysInstanced(
+                    mode,
+                    first as gl::types::GLint,
+                    count as gl::types::GLsizei,
+                    instances as gl::types::GLsizei
+                ),
+            }
+        }
+        self.unbind();
+        Ok(())
+    }
+
+    /// Checks that the vertex array is set up to draw `count` elements from
+    /// `first` and returns the primitive mode to draw them with.
+    fn draw_mode(&self, first: usize, count: usize) -> Result<gl::types::GLenum, Error> {
+        if self.vertices.is_none() {
+            return Err(Error::NoVertices { vao: self.vao_id });
+        }
+        let mode = self.topology.ok_or(Error::NoTopology { vao: self.vao_id })?.gl_enum();
+        if first + count > self.len() {
+            return Err(Error::OutOfRange { vao: self.vao_id, first, count, len: self.len() });
+        }
+        Ok(mode)
+    }
+
+    /// Draws with the command at `index` in `commands`, as the primitive set
+    /// with `set_topology`. Indexed commands need an index buffer and plain
+    /// ones need its absence.
+    pub fn draw_indirect<C: IndirectCommand>(&self, commands: &DrawIndirectBuffer<C>, index: usize) -> Result<(), Error> {
+        if self.vertices.is_none() {
+            return Err(Error::NoVertices { vao: self.vao_id });
+        }
+        let mode = self.topology.ok_or(Error::NoTopology { vao: self.vao_id })?.gl_enum();
+        if C::INDEXED != self.indices.is_some() {
+            let kind = if self.indices.is_some() { "indexed" } else { "non-indexed" };
+            return Err(Error::IndirectCommandMismatch { vao: self.vao_id, kind });
+        }
+        if index >= commands.len() {
+            return Err(Error::OutOfRange { vao: self.vao_id, first: index, count: 1, len: commands.len() });
+        }
+        let offset = (index * std::mem::size_of::<C>()) as *const gl::types::GLvoid;
+        self.bind();
+        commands.bind();
+        unsafe {
+            match self.indices {
+                Some(ref indices) => self.gl.DrawElementsIndirect(mode, indices.kind, offset),
+                None => self.gl.DrawArraysIndirect(mode, offset),
+            }
+        }
+        commands.unbind();
+        self.unbind();
+        Ok(())
+    }
 }
 
 impl Drop for VertexArray {
@@ -109,4 +687,39 @@ impl Drop for VertexArray {
             self.gl.DeleteVertexArrays(1, &mut self.vao_id);
         }
     }
-}
\ No newline at end of file
+}
+
+/// A buffer holding a single uniform block of type `T`, a typed wrapper over
+/// a `Buffer<BufferTypeUniform, u8>` of its std140 bytes. Programs read it
+/// through the binding point it is bound to with
+/// `BindingPoints::bind_base(ub.buffer(), ..)`.
+pub struct UniformBuffer<T>
+where T: UniformBlock {
+    buffer: Buffer<BufferTypeUniform, u8>,
+    _marker: ::std::marker::PhantomData<T>,
+}
+
+impl<T> UniformBuffer<T>
+where T: UniformBlock {
+    pub fn new(gl: &gl::Gl, block: &T) -> UniformBuffer<T> {
+        let buffer = Buffer::new(gl);
+        buffer.bind();
+        buffer.upload_data_with_usage(&block.std140_bytes(), BufferUsage::Dynamic);
+        buffer.unbind();
+        UniformBuffer {
+            buffer,
+            _marker: ::std::marker::PhantomData,
+        }
+    }
+
+    pub fn update(&self, block: &T) {
+        self.buffer.bind();
+        self.buffer.update_data(0, &block.std140_bytes());
+        self.buffer.unbind();
+    }
+
+    /// The underlying bytes, e.g. for binding with `BindingPoints`.
+    pub fn buffer(&self) -> &Buffer<BufferTypeUniform, u8> {
+        &self.buffer
+    }
+}
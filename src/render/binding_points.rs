@@ -0,0 +1,157 @@
+use failure::Fail;
+use gl;
+
+use super::array_buffer::{Buffer, BufferTypeShaderStorage, BufferTypeUniform, IndexedBufferType};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "All {} {} buffer binding points are in use", max, target)]
+    NoFreeBindingPoint { target: &'static str, max: u32 },
+    #[fail(display = "There is no {} buffer binding point {}, the limit is {}", target, binding, max)]
+    BindingOutOfRange { target: &'static str, binding: u32, max: u32 },
+    #[fail(display = "Offset {} into a {} buffer is not a multiple of {}", offset, target, alignment)]
+    MisalignedOffset { target: &'static str, offset: usize, alignment: usize },
+    #[fail(display = "Can't bind an empty range of a {} buffer", target)]
+    EmptyRange { target: &'static str },
+    #[fail(display = "Can't bind {} elements from {} of a buffer with {}", count, first, len)]
+    RangeOutOfBounds { first: usize, count: usize, len: usize },
+}
+
+/// The binding points of one indexed target and which of them are taken.
+struct Target {
+    used: Vec<bool>,
+    offset_alignment: usize,
+}
+
+impl Target {
+    fn query<B: IndexedBufferType>(gl: &gl::Gl) -> Target {
+        let mut max: gl::types::GLint = 0;
+        let mut alignment: gl::types::GLint = 1;
+        unsafe {
+            gl.GetIntegerv(B::MAX_BINDINGS, &mut max);
+            gl.GetIntegerv(B::OFFSET_ALIGNMENT, &mut alignment);
+        }
+        Target {
+            used: vec![false; max.max(0) as usize],
+            offset_alignment: alignment.max(1) as usize,
+        }
+    }
+}
+
+/// Hands out the numbered binding points of the uniform and shader storage
+/// targets and binds buffers to them.
+///
+/// Programs name the point a block reads from, e.g. with
+/// `Program::bind_uniform_block` or `layout (binding = 2)`, so data shared
+/// between programs, such as the camera of a frame, is bound once to a point
+/// allocated here instead of every program picking its own number.
+///
+/// ```ignore
+/// let mut bindings = BindingPoints::new(&gl);
+/// let camera_binding = bindings.allocate::<BufferTypeUniform>()?;
+/// bindings.bind_base(camera.buffer(), camera_binding)?;
+/// program.bind_uniform_block("Camera", camera_binding)?;
+/// ```
+pub struct BindingPoints {
+    gl: gl::Gl,
+    /// Indexed by `IndexedBufferType::TABLE`.
+    targets: [Target; 2],
+}
+
+impl BindingPoints {
+    /// Queries how many binding points there are and the offset alignments
+    /// `bind_range` has to respect, which differ between drivers.
+    pub fn new(gl: &gl::Gl) -> BindingPoints {
+        BindingPoints {
+            gl: gl.clone(),
+            targets: [
+                Target::query::<BufferTypeUniform>(gl),
+                Target::query::<BufferTypeShaderStorage>(gl),
+            ],
+        }
+    }
+
+    fn target<B: IndexedBufferType>(&self) -> &Target {
+        &self.targets[B::TABLE]
+    }
+
+    fn target_mut<B: IndexedBufferType>(&mut self) -> &mut Target {
+        &mut self.targets[B::TABLE]
+    }
+
+    /// Takes the lowest free binding point of `B`.
+    pub fn allocate<B: IndexedBufferType>(&mut self) -> Result<u32, Error> {
+        let target = self.target_mut::<B>();
+        let max = target.used.len() as u32;
+        let binding = target.used.iter().position(|&used| !used)
+            .ok_or(Error::NoFreeBindingPoint { target: B::NAME, max })?;
+        target.used[binding] = true;
+        Ok(binding as u32)
+    }
+
+    /// Gives `binding` back so `allocate` can hand it out again.
+    pub fn release<B: IndexedBufferType>(&mut self, binding: u32) {
+        if let Some(used) = self.target_mut::<B>().used.get_mut(binding as usize) {
+            *used = false;
+        }
+    }
+
+    /// The number of binding points of `B`.
+    pub fn max_bindings<B: IndexedBufferType>(&self) -> u32 {
+        self.target::<B>().used.len() as u32
+    }
+
+    /// The alignment in bytes `bind_range` offsets into a buffer of `B` need.
+    pub fn offset_alignment<B: IndexedBufferType>(&self) -> usize {
+        self.target::<B>().offset_alignment
+    }
+
+    fn check_binding<B: IndexedBufferType>(&self, binding: u32) -> Result<(), Error> {
+        let max = self.max_bindings::<B>();
+        if binding >= max {
+            return Err(Error::BindingOutOfRange { target: B::NAME, binding, max });
+        }
+        Ok(())
+    }
+
+    /// Binds all of `buffer` to `binding`.
+    pub fn bind_base<B, T>(&self, buffer: &Buffer<B, T>, binding: u32) -> Result<(), Error>
+    where B: IndexedBufferType, T: Copy {
+        self.check_binding::<B>(binding)?;
+        unsafe {
+            self.gl.BindBufferBase(B::BUFFER_TYPE, binding, buffer.id());
+        }
+        Ok(())
+    }
+
+    /// Binds `count` elements of `buffer` from `first` on to `binding`, so
+    /// one buffer can hold the blocks of several draws. The byte offset of
+    /// `first` has to be a multiple of `offset_alignment` and the range can't
+    /// be empty.
+    pub fn bind_range<B, T>(&self, buffer: &Buffer<B, T>, binding: u32, first: usize, count: usize) -> Result<(), Error>
+    where B: IndexedBufferType, T: Copy {
+        self.check_binding::<B>(binding)?;
+        if count == 0 {
+            // GL rejects a zero size with GL_INVALID_VALUE and binds nothing
+            return Err(Error::EmptyRange { target: B::NAME });
+        }
+        if first + count > buffer.len() {
+            return Err(Error::RangeOutOfBounds { first, count, len: buffer.len() });
+        }
+        let offset = first * std::mem::size_of::<T>();
+        let alignment = self.offset_alignment::<B>();
+        if offset % alignment != 0 {
+            return Err(Error::MisalignedOffset { target: B::NAME, offset, alignment });
+        }
+        unsafe {
+            self.gl.BindBufferRange(
+                B::BUFFER_TYPE,
+                binding,
+                buffer.id(),
+                offset as gl::types::GLintptr,
+                (count * std::mem::size_of::<T>()) as gl::types::GLsizeiptr
+            );
+        }
+        Ok(())
+    }
+}
@@ -5,65 +5,231 @@ use gl;
 use std::collections::HashMap;
 use font_kit::canvas::{Canvas, Format, RasterizationOptions};
 use font_kit::hinting::HintingOptions;
-use crate::resources::Resources;
+use crate::resources::{self, FontFile, FontSource, Resources};
+use crate::assets::Handle;
+use crate::render::texture::Texture;
 use euclid::Point2D;
 use slog::trace;
 use slog::debug;
-use linked_list::LinkedList;
-use linked_list::Cursor;
-use std::iter::FromIterator;
+
+/// Every printable ASCII character, rasterized when a font does not ask for a
+/// specific character set.
+pub const DEFAULT_CHARS: &str = " ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz01234567890:;'\",<.>/?!@#$%^&*()_-+=[]{}\\|`~";
 
 pub struct Font {
-    font: Vec<font_kit::font::Font>,
+    font: Handle<Vec<font_kit::font::Font>>,
     gl: gl::Gl,
     atlas: Atlas,
+    texture: Texture,
+    glyphs: HashMap<char, Glyph>,
+    line_height: f32,
+}
+
+/// Where a character is in the atlas and how to place it, in pixels.
+#[derive(Copy, Clone, Debug)]
+pub struct Glyph {
+    /// x, y, width and height in the atlas, `None` for blank characters
+    /// such as the space.
+    pub region: Option<nalgebra::Vector4<u32>>,
+    /// From the pen position on the baseline to the top left corner of the
+    /// region, with y pointing down.
+    pub offset: nalgebra::Vector2<i32>,
+    /// How far to move the pen after drawing the glyph.
+    pub advance: f32,
+}
+
+/// A character rasterized by a loader thread.
+struct RasterizedGlyph {
+    character: char,
+    canvas: Canvas,
+    offset: nalgebra::Vector2<i32>,
+    advance: f32,
+}
+
+/// The glyphs of a font rasterized away from the GL context. Packing them into
+/// an atlas and uploading it is left to `Font::from_data`.
+pub struct FontData {
+    file: FontFile,
+    font_size: f32,
+    chars: String,
+    glyphs: Vec<RasterizedGlyph>,
+    line_height: f32,
+}
+
+impl FontData {
+    pub fn load(resources: &Resources, source: &FontSource, font_size: f32, chars: &str, log: &slog::Logger) -> Result<FontData, resources::Error> {
+        let file = resources.resolve_font(source)?;
+        debug!(log, "Using {:?} for {:?}", file.location, source);
+        let fonts = file.faces()?;
+        Ok(FontData {
+            glyphs: rasterize_glyphs(&fonts, font_size, chars, log),
+            line_height: line_height(&fonts, font_size),
+            font_size,
+            chars: chars.into(),
+            file,
+        })
+    }
+
+    /// The font file the glyphs were rasterized from.
+    pub fn file(&self) -> &FontFile {
+        &self.file
+    }
+
+    /// Tells fonts apart in the `Assets` cache, see `font_key`.
+    pub fn key(&self) -> String {
+        font_key(&self.file.key(), self.font_size, &self.chars)
+    }
+}
+
+/// Tells fonts rasterized from the faces cached under `faces_key` apart by
+/// size and character set.
+pub fn font_key(faces_key: &str, font_size: f32, chars: &str) -> String {
+    if chars == DEFAULT_CHARS {
+        format!("{}@{}", faces_key, font_size)
+    } else {
+        format!("{}@{}:{}", faces_key, font_size, chars)
+    }
 }
 
 impl Font {
-    pub fn from_resource(gl: &gl::Gl, resources: &Resources, path: &str, font_size: f32, log: &slog::Logger) -> Font {
-        let chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz01234567890:;'\",<.>/?!@#$%^&*()_-+=[]{}\\|`~";
-        let fonts = resources.load_font(path).unwrap();
-        let hinting_options = HintingOptions::Full(font_size);
-        let canvas_format = Format::Rgb24;
-        let rasterization_options = RasterizationOptions::SubpixelAa;
+    pub fn from_resource(gl: &gl::Gl, resources: &Resources, path: &str, font_size: f32, log: &slog::Logger) -> Result<Font, resources::Error> {
+        let fonts = Handle::new(resources.load_font(path)?);
+        Ok(Font::from_faces(gl, fonts, font_size, DEFAULT_CHARS, log))
+    }
+
+    /// Rasterizes the glyphs of already loaded font faces into an atlas.
+    pub fn from_faces(gl: &gl::Gl, fonts: Handle<Vec<font_kit::font::Font>>, font_size: f32, chars: &str, log: &slog::Logger) -> Font {
+        let glyphs = rasterize_glyphs(&fonts, font_size, chars, log);
+        let line_height = line_height(&fonts, font_size);
+        Font::from_glyphs(gl, fonts, glyphs, line_height, log)
+    }
+
+    /// Finishes a font prepared by a loader thread. font-kit's faces can't
+    /// leave the thread that parsed them, so `fonts` are the faces of
+    /// `data.file()` parsed again on this one, see `Assets::font_file_faces`.
+    pub fn from_data(gl: &gl::Gl, fonts: Handle<Vec<font_kit::font::Font>>, data: FontData, log: &slog::Logger) -> Font {
+        Font::from_glyphs(gl, fonts, data.glyphs, data.line_height, log)
+    }
+
+    fn from_glyphs(gl: &gl::Gl, fonts: Handle<Vec<font_kit::font::Font>>, rasterized: Vec<RasterizedGlyph>, line_height: f32, log: &slog::Logger) -> Font {
         let mut atlas = Atlas::new(512, 512, 1);
-        for font in fonts {
-            trace!(log, "Font loaded: {:?}", font);
-            for character in chars.chars() {
-                let glyph_id = font.glyph_for_char(character).unwrap();
-                let raster_rect = font.raster_bounds(glyph_id,
-                                                    font_size,
-                                                    &Point2D::zero(),
-                                                    hinting_options,
-                                                    rasterization_options)
-                                    .unwrap();
-
-                let mut canvas = Canvas::new(&raster_rect.size.to_u32(), canvas_format);
-
-                let origin = Point2D::new(-raster_rect.origin.x, -raster_rect.origin.y).to_f32();
-                font.rasterize_glyph(&mut canvas,
-                                    glyph_id,
-                                    font_size,
-                                    &origin,
-                                    hinting_options,
-                                    rasterization_options);
-
-                atlas.add_glyph(character, &canvas, log);
+        let mut glyphs = HashMap::new();
+        for glyph in rasterized {
+            let blank = glyph.canvas.size.width == 0 || glyph.canvas.size.height == 0;
+            if !blank {
+                if let Err(e) = atlas.add_glyph(glyph.character, &glyph.canvas, log) {
+                    debug!(log, "Leaving out '{}': {}", glyph.character, e);
+                    continue;
+                }
             }
+            glyphs.insert(glyph.character, Glyph {
+                region: atlas.glyph_map.get(&glyph.character).cloned(),
+                offset: glyph.offset,
+                advance: glyph.advance,
+            });
         }
+        let texture = Texture::from_data(
+            gl,
+            atlas.pixel_width,
+            atlas.pixel_height,
+            &atlas.data,
+        );
         Font {
-            font: resources.load_font(path).unwrap(),
+            font: fonts,
             gl: gl.clone(),
             atlas: atlas,
+            texture: texture,
+            glyphs,
+            line_height,
         }
     }
+
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs.get(&character)
+    }
+
+    /// The distance between two baselines.
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    /// The single channel atlas holding every glyph.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn atlas_size(&self) -> (u32, u32) {
+        (self.atlas.pixel_width, self.atlas.pixel_height)
+    }
+}
+
+fn line_height(fonts: &[font_kit::font::Font], font_size: f32) -> f32 {
+    fonts.first().map_or(font_size, |font| {
+        let metrics = font.metrics();
+        (metrics.ascent - metrics.descent + metrics.line_gap) * font_size / metrics.units_per_em as f32
+    })
+}
+
+fn rasterize_glyphs(fonts: &[font_kit::font::Font], font_size: f32, chars: &str, log: &slog::Logger) -> Vec<RasterizedGlyph> {
+    let hinting_options = HintingOptions::Full(font_size);
+    // The atlas has a single channel, so no subpixel antialiasing
+    let canvas_format = Format::A8;
+    let rasterization_options = RasterizationOptions::GrayscaleAa;
+    for font in fonts {
+        trace!(log, "Font loaded: {:?}", font);
+    }
+    // font-kit's FreeType loader leaves the face set to the pixel size after
+    // rasterizing, and from then on reports bounds and advances already
+    // scaled. Measuring a copy of the face that is never rasterized keeps the
+    // metrics in font units.
+    let measured = fonts.iter()
+        .map(|font| font.handle().and_then(|handle| font_kit::font::Font::from_handle(&handle).ok()))
+        .collect::<Vec<_>>();
+    let mut glyphs = vec!();
+    for character in chars.chars() {
+        // Collections get the character from the first face that has it
+        let found = fonts.iter().zip(&measured)
+            .filter_map(|(font, measured)| font.glyph_for_char(character).map(|glyph_id| (font, measured.as_ref().unwrap_or(font), glyph_id)))
+            .next();
+        let (font, measured, glyph_id) = match found {
+            Some(found) => found,
+            None => {
+                debug!(log, "No glyph for '{}'", character);
+                continue;
+            },
+        };
+        let raster_rect = match measured.raster_bounds(glyph_id, font_size, &Point2D::zero(), hinting_options, rasterization_options) {
+            Ok(raster_rect) => raster_rect,
+            Err(_) => continue,
+        };
+        let units_per_em = measured.metrics().units_per_em as f32;
+        let advance = measured.advance(glyph_id).map_or(0.0, |advance| advance.x * font_size / units_per_em);
+        let mut canvas = Canvas::new(&raster_rect.size.to_u32(), canvas_format);
+        // font-kit can't rasterize blank glyphs, FreeType hands it no bitmap
+        let blank = raster_rect.size.width <= 0 || raster_rect.size.height <= 0;
+        if !blank {
+            if let Err(e) = font.rasterize_glyph(&mut canvas, glyph_id, font_size, &Point2D::zero(), hinting_options, rasterization_options) {
+                debug!(log, "Failed to rasterize '{}': {:?}", character, e);
+                continue;
+            }
+        }
+        glyphs.push(RasterizedGlyph {
+            character,
+            canvas,
+            // The bounds are in font space where y points up
+            offset: nalgebra::Vector2::new(raster_rect.origin.x, -(raster_rect.origin.y + raster_rect.size.height)),
+            advance,
+        });
+    }
+    glyphs
 }
 
 type AtlasRegion = nalgebra::Vector4<u32>;
+/// x, y and width of a segment of the skyline.
 type SkylineNode = nalgebra::Vector3<u32>;
 
 struct Atlas {
-    nodes: LinkedList<SkylineNode>,
+    nodes: Vec<SkylineNode>,
     pixel_width: u32,
     pixel_height: u32,
     pixel_depth: u32,
@@ -77,13 +243,13 @@ impl Atlas {
     pub fn new(width: i32, height: i32, depth: i32) -> Atlas {
         let size = (width * height * depth) as usize;
         Atlas {
-            nodes: LinkedList::from_iter(vec!(SkylineNode::new(1, 1, width as u32 - 2)).into_iter()),
+            nodes: vec!(SkylineNode::new(1, 1, width as u32 - 2)),
             pixel_width: width as u32,
             pixel_height: height as u32,
             pixel_depth: depth as u32,
             data_length: size,
             data_used: 0,
-            data: vec![0; size * 3],
+            data: vec![0; size],
             glyph_map: HashMap::new(),
         }
     }
@@ -111,27 +277,20 @@ impl Atlas {
         );
 
         // The SKYLINE Bin-pack algorithm
-        let mut cit = self.nodes.cursor();
-        let mut best_node: Option<SkylineNode> = None;
+        let mut best_index = None;
         let mut region = AtlasRegion::new(0, 0, required_width, required_height);
         // Current node is our candidate-test node, the one we want to stack our new Glyph on top of.
-        'node_loop: loop {
-            let current_node = match cit.next() {
-                Some(t) => t.clone(),
-                None => break,
-            };
-
+        for (index, current_node) in self.nodes.iter().enumerate() {
             trace!(
                 log,
                 "current node {:?}",
                 current_node,
             );
 
-            let mut remaining_nodes = cit.split();
             // Imagine the candidate node is 10px wide, the new one is 15px wide, and there is only 2px of space
             // to the right of our candidate node. If we place our new node on top of it, the right side will
             // extend past our pixel width boundary.
-            if required_width + current_node[0] as u32 > self.pixel_width {
+            if current_node[0] + required_width > self.pixel_width - 1 {
                 trace!(
                     log,
                     "not enough width after node: {:?}, skipping it",
@@ -139,76 +298,82 @@ impl Atlas {
                 );
                 continue;
             }
-            let mut it = remaining_nodes.iter();
-            let mut max_y_found = 0;
-            let mut width_left = required_width;
-            let mut next_node = current_node;
             // Now we need to check for possible nodes to the right of our new node that we might
             // be intersecting and also that there is enough height to fit our node.
             // Imagine there are two nodes side by side that are both 10px wide, the first one is 10px tall and the second one is 20px tall
-            // If we try to place our new node on the first node then it will overhang it by 5px(remember it is 15px wide).required_width
-            // That overhang will actually intersect with the second node so we can't use this candidate node
-            while width_left > 0 {
-                max_y_found = std::cmp::max(max_y_found, next_node[1]);
-                if max_y_found + required_height > self.pixel_height {
-                    trace!(
-                        log,
-                        "not enough height above node: {:?}, skipping it",
-                        next_node,
-                    );
-                    break;
-                }
-                let overhang_width: i32 = width_left as i32 - next_node[2] as i32;
-                let max_x = next_node[0] + next_node[2];
-                width_left = if overhang_width < 0 {
-                    0
-                } else {
-                    overhang_width as u32
-                };
-                // I don't think this check is actually needed
-                // We aren't changing the x position of our new node here
-                // and we know it already fits because of the first check in the 'node_loop'
-                if max_x as i32 + overhang_width + 1 > self.pixel_width as i32 {
+            // If we try to place our new node on the first node then it will overhang it by 5px, so it has to
+            // sit on top of the second node instead.
+            let mut max_y_found = 0;
+            let mut width_left = required_width as i32;
+            for next_node in &self.nodes[index..] {
+                if width_left <= 0 {
                     break;
                 }
-                match it.next() {
-                    Some(node) => { next_node = node.clone()}
-                    _ => { break; }
-                }
+                max_y_found = std::cmp::max(max_y_found, next_node[1]);
+                width_left -= next_node[2] as i32;
             }
-            if width_left > 0 {
-                cit.splice(&mut remaining_nodes);
+            if width_left > 0 || max_y_found + required_height > self.pixel_height - 1 {
+                trace!(
+                    log,
+                    "not enough space above node: {:?}, skipping it",
+                    current_node,
+                );
                 continue;
             }
 
             // Let's check to see if this current node is the 'best' one, when considering only the ones
             // we have checked in the past.
-            let start_height = max_y_found;
-            let max_height = start_height + required_height;
+            let max_height = max_y_found + required_height;
             if max_height < best_height || (max_height == best_height && current_node[2] < best_width) {
-                best_node = Some(current_node.clone());
+                best_index = Some(index);
                 best_height = max_height;
                 best_width = current_node[2];
                 region.x = current_node[0];
-                region.y = start_height;
+                region.y = max_y_found;
             }
-
-            cit.splice(&mut remaining_nodes);
         }
 
-        match best_node {
-            None => {
-                panic!("No possible candidate found to stack on top of");
+        let best_index = match best_index {
+            Some(best_index) => best_index,
+            None => return Err(failure::err_msg("no space left in the atlas")),
+        };
+        self.insert_node(best_index, SkylineNode::new(region[0], region[1] + region[3], region[2]));
+        self.add_bitmap_to_texture_data(canvas, &region, width, height);
+        self.glyph_map.insert(character, AtlasRegion::new(region[0], region[1], width, height));
+
+        Ok(())
+    }
+
+    /// Puts a new segment on the skyline at `index` and shortens or removes
+    /// the segments after it that it now covers.
+    fn insert_node(&mut self, index: usize, node: SkylineNode) {
+        self.nodes.insert(index, node);
+        let right = node[0] + node[2];
+        let next = index + 1;
+        while next < self.nodes.len() {
+            let covered = self.nodes[next];
+            if covered[0] >= right {
+                break;
             }
-            Some(_) => {
-                let new_node = SkylineNode::new(region[0], region[1] + region[3], region[2]);
-                cit.insert(new_node);
-                self.add_bitmap_to_texture_data(&canvas, &region, width, height);
+            let shrink = right - covered[0];
+            if shrink < covered[2] {
+                self.nodes[next] = SkylineNode::new(right, covered[1], covered[2] - shrink);
+                break;
+            }
+            self.nodes.remove(next);
+        }
+        // Neighbours at the same height become one segment
+        let mut i = 0;
+        while i + 1 < self.nodes.len() {
+            if self.nodes[i][1] == self.nodes[i + 1][1] {
+                self.nodes[i][2] += self.nodes[i + 1][2];
+                self.nodes.remove(i + 1);
+            } else {
+                i += 1;
             }
         }
-
-        Ok(())
     }
+
     fn add_bitmap_to_texture_data(&mut self,
                                   data: &Canvas,
                                   region: &AtlasRegion,
@@ -216,13 +381,92 @@ impl Atlas {
                                   height: u32) {
         for i in 0..height {
             let input_row_offset = data.stride as u32 * i;
-            let output_row = region[1] + i * self.pixel_width;
+            let output_row = (region[1] + i) * self.pixel_width;
             let output_col = region[0];
             for ri in 0..width {
-                let output_idx = (output_row + output_col) as usize;
+                let output_idx = (output_row + output_col + ri) as usize;
                 let input_idx = (input_row_offset + ri) as usize;
                 self.data[output_idx] = data.pixels[input_idx];
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use euclid::Size2D;
+
+    fn canvas(width: u32, height: u32, value: u8) -> Canvas {
+        let mut canvas = Canvas::new(&Size2D::new(width, height), Format::A8);
+        for pixel in canvas.pixels.iter_mut() {
+            *pixel = value;
+        }
+        canvas
+    }
+
+    fn log() -> slog::Logger {
+        slog::Logger::root(slog::Discard, slog::o!())
+    }
+
+    fn overlap(a: &AtlasRegion, b: &AtlasRegion) -> bool {
+        a.x < b.x + b.z && b.x < a.x + a.z && a.y < b.y + b.w && b.y < a.y + a.w
+    }
+
+    #[test]
+    fn packs_glyphs_without_overlap() {
+        let mut atlas = Atlas::new(64, 64, 1);
+        let sizes = [(10, 12), (4, 20), (15, 7), (9, 9), (20, 3), (6, 14), (11, 11), (3, 3), (17, 5), (8, 16)];
+        let chars = "abcdefghij";
+        for (character, &(width, height)) in chars.chars().zip(&sizes) {
+            atlas.add_glyph(character, &canvas(width, height, 1), &log()).unwrap();
+        }
+        let regions = chars.chars().map(|character| atlas.glyph_map[&character]).collect::<Vec<_>>();
+        for (region, &(width, height)) in regions.iter().zip(&sizes) {
+            assert_eq!((region.z, region.w), (width, height));
+            assert!(region.x >= 1 && region.y >= 1);
+            assert!(region.x + region.z < 64 && region.y + region.w < 64, "{:?} is outside the atlas", region);
+        }
+        for (i, a) in regions.iter().enumerate() {
+            for b in &regions[i + 1..] {
+                assert!(!overlap(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn copies_pixels_into_the_region() {
+        let mut atlas = Atlas::new(32, 32, 1);
+        atlas.add_glyph('a', &canvas(5, 4, 7), &log()).unwrap();
+        atlas.add_glyph('b', &canvas(3, 6, 9), &log()).unwrap();
+        for &(character, value) in &[('a', 7), ('b', 9)] {
+            let region = atlas.glyph_map[&character];
+            for y in region.y..region.y + region.w {
+                for x in region.x..region.x + region.z {
+                    assert_eq!(atlas.data[(y * 32 + x) as usize], value);
+                }
+            }
+        }
+        let filled = atlas.data.iter().filter(|&&pixel| pixel != 0).count();
+        assert_eq!(filled, 5 * 4 + 3 * 6);
+    }
+
+    #[test]
+    fn full_atlas_is_an_error() {
+        let mut atlas = Atlas::new(16, 16, 1);
+        atlas.add_glyph('a', &canvas(10, 10, 1), &log()).unwrap();
+        assert!(atlas.add_glyph('b', &canvas(10, 10, 1), &log()).is_err());
+        assert!(atlas.add_glyph('c', &canvas(20, 2, 1), &log()).is_err());
+        assert!(!atlas.glyph_map.contains_key(&'b'));
+        // What is left next to the first glyph can still be used
+        atlas.add_glyph('d', &canvas(2, 2, 1), &log()).unwrap();
+    }
+
+    #[test]
+    fn font_keys_tell_sizes_and_character_sets_apart() {
+        let key = font_key("fonts/DigitalDream.ttf", 16.0, DEFAULT_CHARS);
+        assert_eq!(key, "fonts/DigitalDream.ttf@16");
+        assert_ne!(key, font_key("fonts/DigitalDream.ttf", 24.0, DEFAULT_CHARS));
+        assert_ne!(key, font_key("fonts/DigitalDream.ttf", 16.0, "0123456789"));
+    }
+}
@@ -0,0 +1,177 @@
+use failure::Fail;
+use gl;
+
+use super::array_buffer::BufferType;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to map a ring buffer of {} bytes, persistent mapping needs GL 4.4", size)]
+    MapFailed { size: usize },
+}
+
+/// How many sections a ring buffer has by default: one being written by the
+/// CPU, one queued and one being read by the GPU.
+pub const DEFAULT_SECTIONS: usize = 3;
+
+/// A buffer for data written every frame that is mapped once and stays
+/// mapped, so writes go straight to memory the GPU reads from.
+///
+/// The buffer is split into sections of `capacity` elements of `T`. Every
+/// frame writes into one section and `end_frame` moves on to the next one.
+/// Each section gets a fence when the frame using it ends, and before a
+/// section is written again `end_frame` waits for its fence. With three
+/// sections the GPU can be two frames behind before the CPU has to wait,
+/// and the CPU never overwrites data the GPU is still reading.
+///
+/// ```ignore
+/// let mut lines = RingBuffer::<BufferTypeArray, LineVertex>::new(&gl, 4096, DEFAULT_SECTIONS)?;
+/// vao.set_ring_buffer(&lines);
+/// vao.set_topology(Topology::Lines);
+/// // every frame
+/// let first = lines.write(&vertices).expect("too many lines");
+/// vao.draw_range(first, vertices.len())?;
+/// lines.end_frame();
+/// ```
+pub struct RingBuffer<B, T>
+where B: BufferType, T: Copy {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    data: *mut T,
+    capacity: usize,
+    sections: Vec<Option<gl::types::GLsync>>,
+    section: usize,
+    /// Elements written to the current section.
+    used: usize,
+    _marker: ::std::marker::PhantomData<B>,
+}
+
+impl<B, T> RingBuffer<B, T>
+where B: BufferType, T: Copy {
+    /// Allocates `sections` sections of `capacity` elements each with
+    /// `glBufferStorage` and maps all of them.
+    pub fn new(gl: &gl::Gl, capacity: usize, sections: usize) -> Result<RingBuffer<B, T>, Error> {
+        let size = capacity * sections * std::mem::size_of::<T>();
+        let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+        let mut id: gl::types::GLuint = 0;
+        let data = unsafe {
+            gl.GenBuffers(1, &mut id);
+            gl.BindBuffer(B::BUFFER_TYPE, id);
+            gl.BufferStorage(B::BUFFER_TYPE, size as gl::types::GLsizeiptr, std::ptr::null(), flags);
+            let data = gl.MapBufferRange(B::BUFFER_TYPE, 0, size as gl::types::GLsizeiptr, flags);
+            gl.BindBuffer(B::BUFFER_TYPE, 0);
+            data as *mut T
+        };
+        if data.is_null() {
+            unsafe {
+                gl.DeleteBuffers(1, &id);
+            }
+            return Err(Error::MapFailed { size });
+        }
+        Ok(RingBuffer {
+            gl: gl.clone(),
+            id,
+            data,
+            capacity,
+            sections: vec![None; sections],
+            section: 0,
+            used: 0,
+            _marker: ::std::marker::PhantomData,
+        })
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            self.gl.BindBuffer(B::BUFFER_TYPE, self.id);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            self.gl.BindBuffer(B::BUFFER_TYPE, 0);
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    /// The number of elements each frame can write.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn sections(&self) -> usize {
+        self.sections.len()
+    }
+
+    /// Appends `data` to the section of the current frame and returns the
+    /// index of its first element in the whole buffer, which is what draw
+    /// calls take as `first` or base vertex. `None` if the section is full.
+    pub fn write(&mut self, data: &[T]) -> Option<usize> {
+        if self.used + data.len() > self.capacity {
+            return None;
+        }
+        let first = self.section * self.capacity + self.used;
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.data.add(first), data.len());
+        }
+        self.used += data.len();
+        Some(first)
+    }
+
+    /// Like `write`, but when the current section is full it is ended as by
+    /// `end_frame` and `data` goes to the next one. `None` only if `data`
+    /// is longer than `capacity`.
+    pub fn write_or_advance(&mut self, data: &[T]) -> Option<usize> {
+        if data.len() > self.capacity {
+            return None;
+        }
+        if self.used + data.len() > self.capacity {
+            self.end_frame();
+        }
+        self.write(data)
+    }
+
+    /// Call once the draws reading this frame's data have been issued. Fences
+    /// the current section and moves on to the next one, waiting for the GPU
+    /// to finish reading it first if it's still in use.
+    pub fn end_frame(&mut self) {
+        unsafe {
+            let fence = self.gl.FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+            if let Some(old) = self.sections[self.section].replace(fence) {
+                self.gl.DeleteSync(old);
+            }
+        }
+        self.section = (self.section + 1) % self.sections.len();
+        self.used = 0;
+        if let Some(fence) = self.sections[self.section].take() {
+            wait(&self.gl, fence);
+        }
+    }
+}
+
+/// Blocks until `fence` is signaled and deletes it.
+fn wait(gl: &gl::Gl, fence: gl::types::GLsync) {
+    const TIMEOUT_NS: gl::types::GLuint64 = 1_000_000_000;
+    unsafe {
+        // Anything else means signaled, or the context is gone and waiting
+        // won't help
+        while gl.ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, TIMEOUT_NS) == gl::TIMEOUT_EXPIRED {}
+        gl.DeleteSync(fence);
+    }
+}
+
+impl<B, T> Drop for RingBuffer<B, T>
+where B: BufferType, T: Copy {
+    fn drop(&mut self) {
+        unsafe {
+            for fence in self.sections.drain(..).flatten() {
+                self.gl.DeleteSync(fence);
+            }
+            self.gl.BindBuffer(B::BUFFER_TYPE, self.id);
+            self.gl.UnmapBuffer(B::BUFFER_TYPE);
+            self.gl.BindBuffer(B::BUFFER_TYPE, 0);
+            self.gl.DeleteBuffers(1, &self.id);
+        }
+    }
+}
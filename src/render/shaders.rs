@@ -1,30 +1,93 @@
+pub mod binary_cache;
+pub mod check;
+pub mod diagnostics;
+pub mod preprocessor;
+pub mod reflection;
+pub mod uniform;
+
+use std::cell::{Cell, Ref, RefCell};
 use std::ffi::CStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use crate::resources::Resources;
-use std::ffi::CString;
+use crate::resources::shader_stages;
 use crate::resources;
 use failure::Fail;
 
+use self::binary_cache::BinaryCache;
+use self::preprocessor::Preprocessed;
+use super::uniform_block::UniformBlock;
+
+pub use self::diagnostics::{Diagnostic, Severity, ShaderStage};
+pub use self::reflection::{ActiveVariable, Reflection};
+pub use self::uniform::{Sampler, UniformValue};
+
 #[derive(Debug, Fail)]
 pub enum Error {
     #[fail(display = "Failed to load resource {}", name)]
-    ResourceLoad { name: String, inner: resources::Error },
+    ResourceLoad { name: String, #[cause] inner: resources::Error },
     #[fail(display = "Can not determine shader type for resource {}", name)]
     CanNotDetermineShaderTypeForResource { name: String },
-    #[fail(display = "Failed to compile shader {}: {}", name, message)]
-    CompileError { name: String, message: String },
-    #[fail(display = "Failed to link program {}: {}", name, message)]
-    LinkError { name: String, message: String },
+    #[fail(display = "Include cycle in shader {}: {}", name, chain)]
+    IncludeCycle { name: String, chain: String },
+    #[fail(display = "{}:{}: {}", name, line, message)]
+    InvalidDirective { name: String, line: usize, message: String },
+    #[fail(display = "Invalid define {:?} for shader {}", define, name)]
+    InvalidDefine { name: String, define: String },
+    #[fail(display = "Shader {} defines {} more than once", name, define)]
+    DuplicateDefine { name: String, define: String },
+    /// `message` holds the diagnostics rendered for printing, or the raw
+    /// info log if the driver's format wasn't recognised.
+    #[fail(display = "Failed to compile shader {}\n{}", name, message)]
+    CompileError { name: String, message: String, diagnostics: Vec<Diagnostic> },
+    #[fail(display = "Failed to link program {}\n{}", name, message)]
+    LinkError { name: String, message: String, diagnostics: Vec<Diagnostic> },
+    #[fail(display = "Program {} mixes a compute shader with graphics stages", name)]
+    MixedComputeAndGraphics { name: String },
+    #[fail(display = "Program {} is not a compute program", name)]
+    NotCompute { name: String },
+    #[fail(display = "Program {} has no active uniform {}", program, uniform)]
+    UnknownUniform { program: String, uniform: String },
+    #[fail(display = "Uniform {} of program {} is a {}, not a {}", uniform, program, expected, found)]
+    UniformTypeMismatch { program: String, uniform: String, expected: &'static str, found: &'static str },
+    #[fail(display = "Program {} has no active uniform block {}", program, block)]
+    UnknownUniformBlock { program: String, block: String },
+    #[fail(display = "Uniform block {} of program {} is {} bytes, not {}", block, program, expected, found)]
+    UniformBlockSizeMismatch { program: String, block: String, expected: usize, found: usize },
+}
+
+/// Reads an info log of `len` bytes, including the terminating nul, through
+/// `get_info_log`.
+fn read_info_log<F>(len: gl::types::GLint, get_info_log: F) -> String
+where F: FnOnce(gl::types::GLsizei, *mut gl::types::GLsizei, *mut gl::types::GLchar) {
+    let mut buffer = vec![0u8; len.max(1) as usize];
+    let mut written: gl::types::GLsizei = 0;
+    get_info_log(buffer.len() as gl::types::GLsizei, &mut written, buffer.as_mut_ptr() as *mut gl::types::GLchar);
+    buffer.truncate(written.max(0) as usize);
+    String::from_utf8_lossy(&buffer).into_owned()
 }
 
-fn get_cstring_with_len(len: usize) -> CString {
-    let mut buffer:Vec<u8> = Vec::with_capacity(len as usize + 1);
-    buffer.extend([b' '].iter().cycle().take(len as usize));
-    return unsafe { CString::from_vec_unchecked(buffer)};
+fn link_error(name: &str, log: &str) -> Error {
+    let diagnostics = diagnostics::parse_log(log, None, None);
+    Error::LinkError {
+        name: name.into(),
+        message: rendered_or_raw(&diagnostics, None, log),
+        diagnostics,
+    }
+}
+
+fn rendered_or_raw(diagnostics: &[Diagnostic], source_map: Option<&preprocessor::SourceMap>, log: &str) -> String {
+    if diagnostics.is_empty() {
+        log.trim_end().into()
+    } else {
+        diagnostics::render(diagnostics, source_map)
+    }
 }
 
 pub struct Shader {
     id: gl::types::GLuint,
+    kind: gl::types::GLenum,
     gl: gl::Gl,
+    sources: Vec<String>,
 }
 
 impl Drop for Shader {
@@ -40,28 +103,41 @@ impl Shader {
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
+
+    /// The stage, e.g. `gl::VERTEX_SHADER`.
+    pub fn kind(&self) -> gl::types::GLenum {
+        self.kind
+    }
+
+    /// The resources this shader was compiled from, its own file first and
+    /// then everything it includes.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
     pub fn from_res(gl: &gl::Gl, resources: &Resources, name: &str) -> Result<Shader, Error> {
-        const POSSIBLE_EXT: [(&str, gl::types::GLenum); 2] = [
-            (".vert", gl::VERTEX_SHADER),
-            (".frag", gl::FRAGMENT_SHADER),
-        ];
-        let shader_kind = POSSIBLE_EXT.iter()
-            .find(|&&(file_extension, _)| {
-                name.ends_with(file_extension)
-            })
-            .map(|&(_, kind)| kind)
-            .ok_or_else(|| Error::CanNotDetermineShaderTypeForResource { name: name.into() })?;
+        Shader::from_res_with_defines(gl, resources, name, &[])
+    }
 
-        let source = resources.load_cstring(name)
-            .map_err(|e| Error::ResourceLoad {
-                name: name.into(),
-                inner: e
-            })?;
+    /// Compiles a shader resource with the given macros defined right after
+    /// its `#version` line.
+    pub fn from_res_with_defines(gl: &gl::Gl, resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Shader, Error> {
+        let stage = Stage::load(resources, name, defines)?;
+        Shader::from_stage(gl, &stage)
+    }
 
-        Shader::from_source(gl, &source, shader_kind).map_err(|message| Error::CompileError {
-            name: name.into(),
-            message
-        })
+    fn from_stage(gl: &gl::Gl, stage: &Stage) -> Result<Shader, Error> {
+        let source_map = &stage.preprocessed.source_map;
+        let mut shader = Shader::from_source(gl, &stage.preprocessed.source, stage.kind).map_err(|log| {
+            let diagnostics = diagnostics::parse_log(&log, ShaderStage::from_gl(stage.kind), Some(source_map));
+            Error::CompileError {
+                name: stage.name.clone(),
+                message: rendered_or_raw(&diagnostics, Some(source_map), &log),
+                diagnostics,
+            }
+        })?;
+        shader.sources = source_map.files().to_vec();
+        Ok(shader)
     }
 
     pub fn from_source(gl: &gl::Gl, source: &CStr, kind: gl::types::GLenum) -> Result<Shader, String> {
@@ -74,80 +150,344 @@ impl Shader {
         unsafe {
             gl.GetShaderiv(id, gl::COMPILE_STATUS, &mut result);
         }
-        println!("{}", result);
         if result == 0 {
             let mut len: gl::types::GLint = 0;
             unsafe {
                 gl.GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len);
             }
-            let error = get_cstring_with_len(len as usize);
+            let log = read_info_log(len, |buffer_len, written, log| unsafe {
+                gl.GetShaderInfoLog(id, buffer_len, written, log)
+            });
             unsafe {
-                gl.GetShaderInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut gl::types::GLchar);
+                gl.DeleteShader(id);
             }
-            return Err(error.to_string_lossy().into_owned());
+            return Err(log);
         }
-        return Ok(Shader { id: id, gl: gl.clone() });
+        return Ok(Shader { id: id, kind: kind, gl: gl.clone(), sources: vec!() });
+    }
+}
+
+/// The preprocessed source of a single stage, ready to be compiled.
+struct Stage {
+    name: String,
+    kind: gl::types::GLenum,
+    preprocessed: Preprocessed,
+}
+
+impl Stage {
+    fn load(resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Stage, Error> {
+        let kind = shader_stages::extension(name)
+            .map(stage_kind)
+            .ok_or_else(|| Error::CanNotDetermineShaderTypeForResource { name: name.into() })?;
+        Ok(Stage {
+            name: name.into(),
+            kind,
+            preprocessed: preprocessor::preprocess(resources, name, defines)?,
+        })
     }
 }
 
+/// Counts successful links across all programs, see `Program::link_serial`.
+static LINKS: AtomicUsize = AtomicUsize::new(0);
+
 pub struct Program {
     id: gl::types::GLuint,
     gl: gl::Gl,
+    name: Option<String>,
+    /// The resource of every stage, which `reload` compiles again.
+    stage_files: Vec<String>,
+    defines: Vec<(String, String)>,
+    sources: RefCell<Vec<String>>,
+    reflection: RefCell<Reflection>,
+    compute: Cell<bool>,
+    link_serial: Cell<usize>,
+    /// Binding points assigned with `bind_uniform_block`, which linking
+    /// resets.
+    uniform_blocks: RefCell<Vec<(String, u32)>>,
 }
 
 impl Program {
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
+
+    /// The resource name this program was loaded from, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The resources holding the source of every stage of this program,
+    /// including the files they include, as of the last successful link.
+    pub fn resource_names(&self) -> Vec<String> {
+        self.sources.borrow().clone()
+    }
+
+    /// The macros this program was compiled with.
+    pub fn defines(&self) -> &[(String, String)] {
+        &self.defines
+    }
+
     pub fn from_res(gl: &gl::Gl, resources: &Resources, name: &str) -> Result<Box<Program>, Error> {
-        const POSSIBLE_EXT: [&str; 2] = [
-            ".vert",
-            ".frag",
-        ];
-        let shaders = POSSIBLE_EXT.iter()
-            .map(|file_extension| {
-                Shader::from_res(gl, resources, &format!("{}{}", name, file_extension))
-            }).collect::<Result<Vec<Shader>, Error>>()?;
-
-        Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
-            name: name.into(),
-            message,
-        })
+        Program::from_res_with_defines(gl, resources, name, &[])
+    }
+
+    /// Builds a variant of a program with extra macros defined in every
+    /// stage, e.g. `&[("USE_TEXTURE", "1"), ("MAX_LIGHTS", "8")]`.
+    ///
+    /// When `resources` has a cache directory, a previously linked binary of
+    /// the exact same sources is used instead of compiling, and freshly
+    /// linked programs are added to the cache.
+    pub fn from_res_with_defines(gl: &gl::Gl, resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Box<Program>, Error> {
+        let stage_files = Program::stage_resource_names(resources, name)?;
+        Program::build(gl, resources, name, stage_files, defines)
+    }
+
+    /// Builds a program out of stage files that don't share a name, such as
+    /// a fragment shader paired with a vertex shader used by many programs.
+    /// The stage of each file is taken from its extension and `name` is only
+    /// used in messages.
+    pub fn from_stage_files(gl: &gl::Gl, resources: &Resources, name: &str, stage_files: &[&str], defines: &[(&str, &str)]) -> Result<Box<Program>, Error> {
+        let stage_files = stage_files.iter().map(|&file| file.to_owned()).collect();
+        Program::build(gl, resources, name, stage_files, defines)
+    }
+
+    fn build(gl: &gl::Gl, resources: &Resources, name: &str, stage_files: Vec<String>, defines: &[(&str, &str)]) -> Result<Box<Program>, Error> {
+        let stages = Program::load_stages(resources, &stage_files, defines)?;
+        let mut program = Program::create(gl);
+        program.name = Some(name.into());
+        program.stage_files = stage_files;
+        program.defines = defines.iter().map(|&(define, value)| (define.into(), value.into())).collect();
+
+        let cache = resources.cache_dir().map(|dir| program.binary_cache(dir, &stages));
+        let cached = cache.as_ref().map_or(false, |cache| cache.load(gl, program.id));
+        if !cached {
+            let shaders = stages.iter()
+                .map(|stage| Shader::from_stage(gl, stage))
+                .collect::<Result<Vec<Shader>, Error>>()?;
+            program.link(&shaders, cache.as_ref()).map_err(|log| link_error(name, &log))?;
+        }
+        program.linked(&stages);
+        Ok(program)
+    }
+
+    /// Recompiles and relinks this program from its resources, keeping the
+    /// same program id. If any stage fails to compile or the result fails to
+    /// link, the previously linked program stays in place.
+    pub fn reload(&self, resources: &Resources) -> Result<(), Error> {
+        let name = match self.name {
+            Some(ref name) => name,
+            None => return Ok(()),
+        };
+        let defines = self.defines.iter()
+            .map(|(define, value)| (define.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        let stages = Program::load_stages(resources, &self.stage_files, &defines)?;
+        let shaders = stages.iter()
+            .map(|stage| Shader::from_stage(&self.gl, stage))
+            .collect::<Result<Vec<Shader>, Error>>()?;
 
+        // Linking an in-use program that fails would throw away the working
+        // executable, so make sure the new stages link on their own first.
+        Program::from_shaders(&self.gl, &shaders[..]).map_err(|log| link_error(name, &log))?;
+        let cache = resources.cache_dir().map(|dir| self.binary_cache(dir, &stages));
+        self.link(&shaders, cache.as_ref()).map_err(|log| link_error(name, &log))?;
+        self.linked(&stages);
+        Ok(())
     }
+
     pub fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<Box<Program>, String> {
-        let id = unsafe { gl.CreateProgram() };
-        for shader in shaders {
-            println!("attaching shader id: {}", shader.id());
+        let program = Program::create(gl);
+        link_shaders(gl, program.id, shaders)?;
+        *program.reflection.borrow_mut() = Reflection::query(gl, program.id);
+        program.compute.set(shaders.iter().any(|shader| shader.kind() == gl::COMPUTE_SHADER));
+        program.link_serial.set(LINKS.fetch_add(1, Ordering::Relaxed) + 1);
+        Ok(program)
+    }
+
+    fn create(gl: &gl::Gl) -> Box<Program> {
+        Box::new(Program {
+            id: unsafe { gl.CreateProgram() },
+            gl: gl.clone(),
+            name: None,
+            stage_files: vec!(),
+            defines: vec!(),
+            sources: RefCell::new(vec!()),
+            reflection: RefCell::new(Reflection::default()),
+            compute: Cell::new(false),
+            link_serial: Cell::new(0),
+            uniform_blocks: RefCell::new(vec!()),
+        })
+    }
+
+    /// The cache entry for the stages, one per name and set of defines.
+    fn binary_cache(&self, dir: &std::path::Path, stages: &[Stage]) -> BinaryCache {
+        let mut variant = self.name.clone().unwrap_or_default();
+        for (define, value) in &self.defines {
+            variant.push_str(&format!("\0{}={}", define, value));
+        }
+        let sources = stages.iter()
+            .map(|stage| (stage.kind, stage.preprocessed.source.as_c_str()))
+            .collect::<Vec<_>>();
+        BinaryCache::new(&self.gl, dir, &variant, &sources)
+    }
+
+    /// Links the shaders into this program and stores the result in `cache`.
+    fn link(&self, shaders: &[Shader], cache: Option<&BinaryCache>) -> Result<(), String> {
+        if cache.is_some() {
             unsafe {
-                gl.AttachShader(id, shader.id());
+                self.gl.ProgramParameteri(self.id, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as gl::types::GLint);
             }
         }
-        let mut result: gl::types::GLint = 1;
-        unsafe {
-            gl.LinkProgram(id);
-            gl.GetProgramiv(id, gl::LINK_STATUS, &mut result);
+        link_shaders(&self.gl, self.id, shaders)?;
+        if let Some(cache) = cache {
+            // The cache is only an optimisation, failing to write it is fine
+            let _ = cache.store(&self.gl, self.id);
         }
+        Ok(())
+    }
 
-        if result == 0 {
-            let mut len: gl::types::GLint = 0;
-            unsafe {
-                gl.GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut len);
+    /// Refreshes everything derived from the sources after a successful link.
+    fn linked(&self, stages: &[Stage]) {
+        let mut sources: Vec<String> = vec!();
+        for name in stages.iter().flat_map(|stage| stage.preprocessed.source_map.files()) {
+            if !sources.contains(name) {
+                sources.push(name.clone());
             }
-            let error = get_cstring_with_len(len as usize);
-            unsafe {
-                gl.GetProgramInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut gl::types::GLchar);
-            }
-            return Err(error.to_string_lossy().into_owned());
         }
+        *self.sources.borrow_mut() = sources;
+        *self.reflection.borrow_mut() = Reflection::query(&self.gl, self.id);
+        self.compute.set(stages.iter().any(|stage| stage.kind == gl::COMPUTE_SHADER));
+        self.link_serial.set(LINKS.fetch_add(1, Ordering::Relaxed) + 1);
+        for &(ref block, binding) in self.uniform_blocks.borrow().iter() {
+            // Blocks removed by the reload simply stop being bound
+            let _ = self.uniform_block_index(block).map(|index| unsafe {
+                self.gl.UniformBlockBinding(self.id, index, binding);
+            });
+        }
+    }
 
+    /// Changes with every successful link and is never shared by two links,
+    /// even of different programs, so it tells whether uniforms read from the
+    /// program are still current.
+    pub fn link_serial(&self) -> usize {
+        self.link_serial.get()
+    }
 
-        for shader in shaders {
-            unsafe {
-                gl.DetachShader(id, shader.id());
-            }
+    /// The active uniforms and attributes as of the last successful link.
+    pub fn reflection(&self) -> Ref<'_, Reflection> {
+        self.reflection.borrow()
+    }
+
+    /// Makes the uniform block `name` read from the uniform buffer bound to
+    /// `binding`, after checking that the block is as large as `T`.
+    pub fn bind_uniform_block<T: UniformBlock>(&self, name: &str, binding: u32) -> Result<(), Error> {
+        let index = self.uniform_block_index(name).ok_or_else(|| Error::UnknownUniformBlock {
+            program: self.display_name().into(),
+            block: name.into(),
+        })?;
+        let mut size: gl::types::GLint = 0;
+        unsafe {
+            self.gl.GetActiveUniformBlockiv(self.id, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+        }
+        if size as usize != T::SIZE {
+            return Err(Error::UniformBlockSizeMismatch {
+                program: self.display_name().into(),
+                block: name.into(),
+                expected: size as usize,
+                found: T::SIZE,
+            });
+        }
+        unsafe {
+            self.gl.UniformBlockBinding(self.id, index, binding);
+        }
+        let mut uniform_blocks = self.uniform_blocks.borrow_mut();
+        uniform_blocks.retain(|(block, _)| block != name);
+        uniform_blocks.push((name.into(), binding));
+        Ok(())
+    }
+
+    fn uniform_block_index(&self, name: &str) -> Option<gl::types::GLuint> {
+        let name = std::ffi::CString::new(name).ok()?;
+        let index = unsafe { self.gl.GetUniformBlockIndex(self.id, name.as_ptr()) };
+        if index == gl::INVALID_INDEX {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    /// Sets a uniform by name. The program does not need to be active.
+    ///
+    /// ```ignore
+    /// program.set_uniform("cameraToClipMatrix", &Matrix4::identity())?;
+    /// program.set_uniform("fontTexture", &Sampler(0))?;
+    /// ```
+    pub fn set_uniform<V: UniformValue + ?Sized>(&self, name: &str, value: &V) -> Result<(), Error> {
+        let reflection = self.reflection.borrow();
+        let uniform = reflection.uniform(name).ok_or_else(|| Error::UnknownUniform {
+            program: self.display_name().into(),
+            uniform: name.into(),
+        })?;
+        if !value.accepts(uniform.kind) {
+            return Err(Error::UniformTypeMismatch {
+                program: self.display_name().into(),
+                uniform: name.into(),
+                expected: uniform.type_name(),
+                found: value.type_name(),
+            });
+        }
+        unsafe {
+            value.set(&self.gl, self.id, uniform.location);
+        }
+        Ok(())
+    }
+
+    /// Like `set_uniform`, but does nothing if the program has no active
+    /// uniform `name`. For uniforms a shader may leave out or the compiler
+    /// may optimize away, such as those of edited or Shadertoy shaders.
+    pub fn set_uniform_if_active<V: UniformValue + ?Sized>(&self, name: &str, value: &V) -> Result<(), Error> {
+        match self.set_uniform(name, value) {
+            Err(Error::UnknownUniform { .. }) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Reads back the current value of a `float` or float vector uniform, one
+    /// element per component.
+    pub fn float_uniform(&self, name: &str) -> Result<Vec<f32>, Error> {
+        let reflection = self.reflection.borrow();
+        let uniform = reflection.uniform(name).ok_or_else(|| Error::UnknownUniform {
+            program: self.display_name().into(),
+            uniform: name.into(),
+        })?;
+        let components = reflection::float_components(uniform.kind).ok_or_else(|| Error::UniformTypeMismatch {
+            program: self.display_name().into(),
+            uniform: name.into(),
+            expected: uniform.type_name(),
+            found: "float vector",
+        })?;
+        let mut value = vec![0.0; 4];
+        unsafe {
+            self.gl.GetUniformfv(self.id, uniform.location, value.as_mut_ptr());
         }
-        return Ok(Box::new(Program { id: id, gl: gl.clone()}));
+        value.truncate(components);
+        Ok(value)
+    }
+
+    /// The local work group size if this is a compute program.
+    pub fn work_group_size(&self) -> Option<[i32; 3]> {
+        if !self.compute.get() {
+            return None;
+        }
+        let mut size = [0; 3];
+        unsafe {
+            self.gl.GetProgramiv(self.id, gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr());
+        }
+        Some(size)
+    }
+
+    fn display_name(&self) -> &str {
+        self.name().unwrap_or("<unnamed>")
     }
 
     pub fn activate(&self) {
@@ -155,6 +495,112 @@ impl Program {
             self.gl.UseProgram(self.id);
         }
     }
+
+    /// The stage files that make up the program `name`, see
+    /// `resources::shader_stages`.
+    fn stage_resource_names(resources: &Resources, name: &str) -> Result<Vec<String>, Error> {
+        shader_stages::stage_files(resources, name).map_err(|e| match e {
+            resources::Error::MixedShaderStages { name } => Error::MixedComputeAndGraphics { name },
+            inner => Error::ResourceLoad { name: name.into(), inner },
+        })
+    }
+
+    fn stages_from_res(resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Vec<Stage>, Error> {
+        Program::load_stages(resources, &Program::stage_resource_names(resources, name)?, defines)
+    }
+
+    fn load_stages(resources: &Resources, stage_files: &[String], defines: &[(&str, &str)]) -> Result<Vec<Stage>, Error> {
+        stage_files.iter()
+            .map(|resource_name| Stage::load(resources, resource_name, defines))
+            .collect::<Result<Vec<Stage>, Error>>()
+    }
+}
+
+/// The GL shader type of a stage extension from `shader_stages::EXTENSIONS`.
+fn stage_kind(file_extension: &str) -> gl::types::GLenum {
+    match file_extension {
+        ".vert" => gl::VERTEX_SHADER,
+        ".tesc" => gl::TESS_CONTROL_SHADER,
+        ".tese" => gl::TESS_EVALUATION_SHADER,
+        ".geom" => gl::GEOMETRY_SHADER,
+        ".frag" => gl::FRAGMENT_SHADER,
+        ".comp" => gl::COMPUTE_SHADER,
+        other => unreachable!("unknown shader stage extension {}", other),
+    }
+}
+
+/// A program made of a single compute shader, loaded from `<name>.comp`.
+pub struct ComputeProgram {
+    program: Program,
+}
+
+impl ComputeProgram {
+    pub fn from_res(gl: &gl::Gl, resources: &Resources, name: &str) -> Result<ComputeProgram, Error> {
+        ComputeProgram::from_program(*Program::from_res(gl, resources, name)?)
+    }
+
+    pub fn from_res_with_defines(gl: &gl::Gl, resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<ComputeProgram, Error> {
+        ComputeProgram::from_program(*Program::from_res_with_defines(gl, resources, name, defines)?)
+    }
+
+    fn from_program(program: Program) -> Result<ComputeProgram, Error> {
+        if program.work_group_size().is_none() {
+            return Err(Error::NotCompute { name: program.display_name().into() });
+        }
+        Ok(ComputeProgram { program })
+    }
+
+    /// The local size declared in the shader.
+    pub fn work_group_size(&self) -> [i32; 3] {
+        self.program.work_group_size().unwrap()
+    }
+
+    /// Activates the program and launches `x * y * z` work groups.
+    pub fn dispatch(&self, x: u32, y: u32, z: u32) {
+        self.program.activate();
+        unsafe {
+            self.program.gl.DispatchCompute(x, y, z);
+        }
+    }
+}
+
+impl std::ops::Deref for ComputeProgram {
+    type Target = Program;
+
+    fn deref(&self) -> &Program {
+        &self.program
+    }
+}
+
+fn link_shaders(gl: &gl::Gl, id: gl::types::GLuint, shaders: &[Shader]) -> Result<(), String> {
+    for shader in shaders {
+        unsafe {
+            gl.AttachShader(id, shader.id());
+        }
+    }
+    let mut result: gl::types::GLint = 1;
+    unsafe {
+        gl.LinkProgram(id);
+        gl.GetProgramiv(id, gl::LINK_STATUS, &mut result);
+    }
+
+    for shader in shaders {
+        unsafe {
+            gl.DetachShader(id, shader.id());
+        }
+    }
+
+    if result == 0 {
+        let mut len: gl::types::GLint = 0;
+        unsafe {
+            gl.GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut len);
+        }
+        return Err(read_info_log(len, |buffer_len, written, log| unsafe {
+            gl.GetProgramInfoLog(id, buffer_len, written, log)
+        }));
+    }
+
+    Ok(())
 }
 
 impl Drop for Program {
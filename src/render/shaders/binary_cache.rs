@@ -0,0 +1,201 @@
+//! Caches linked program binaries on disk so later launches can skip
+//! compiling.
+//!
+//! Entries are keyed by a hash of the preprocessed source of every stage and
+//! the driver's vendor, renderer and version strings, so editing a shader or
+//! updating the driver simply misses the cache. Each file holds a magic
+//! number, the binary format and the binary itself.
+//!
+//! Every program variant, a program name with its defines, keeps a single
+//! entry. Storing a new binary removes the ones older sources left behind,
+//! so editing shaders doesn't grow the cache forever.
+
+use std::ffi::CStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+
+const MAGIC: &[u8; 4] = b"RPGB";
+
+pub struct BinaryCache {
+    path: PathBuf,
+    /// The file name prefix shared by every entry of the program variant.
+    variant: String,
+}
+
+impl BinaryCache {
+    /// The cache entry for the program `variant` made of the given stages.
+    pub fn new(gl: &gl::Gl, dir: &Path, variant: &str, stages: &[(GLenum, &CStr)]) -> BinaryCache {
+        let mut variant_hash = Fnv1a::new();
+        variant_hash.write(variant.as_bytes());
+        let mut hash = Fnv1a::new();
+        for &string in &[gl::VENDOR, gl::RENDERER, gl::VERSION] {
+            hash.write(gl_string(gl, string).as_bytes());
+            hash.write(&[0]);
+        }
+        for &(kind, source) in stages {
+            hash.write(&kind.to_le_bytes());
+            hash.write(source.to_bytes_with_nul());
+        }
+        BinaryCache::at(dir, variant_hash.finish(), hash.finish())
+    }
+
+    fn at(dir: &Path, variant: u64, sources: u64) -> BinaryCache {
+        let variant = format!("{:016x}-", variant);
+        BinaryCache {
+            path: dir.join(format!("{}{:016x}.bin", variant, sources)),
+            variant,
+        }
+    }
+
+    /// Loads the cached binary into `program`. Returns false when there is no
+    /// entry or the driver rejects it, in which case the program has to be
+    /// compiled from source.
+    pub fn load(&self, gl: &gl::Gl, program: GLuint) -> bool {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(_) => return false,
+        };
+        if data.len() < 8 || &data[..4] != MAGIC {
+            let _ = fs::remove_file(&self.path);
+            return false;
+        }
+        let format = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        let binary = &data[8..];
+        let mut linked: GLint = 0;
+        unsafe {
+            gl.ProgramBinary(program, format, binary.as_ptr() as *const _, binary.len() as GLsizei);
+            gl.GetProgramiv(program, gl::LINK_STATUS, &mut linked);
+        }
+        if linked == 0 {
+            // Stale for this driver even though the strings matched, it gets
+            // replaced after the program is compiled again.
+            let _ = fs::remove_file(&self.path);
+        }
+        linked != 0
+    }
+
+    /// Stores the binary of a linked program. The program must have been
+    /// linked with `PROGRAM_BINARY_RETRIEVABLE_HINT` set.
+    pub fn store(&self, gl: &gl::Gl, program: GLuint) -> io::Result<()> {
+        let mut length: GLint = 0;
+        unsafe {
+            gl.GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut length);
+        }
+        if length <= 0 {
+            // The driver doesn't support any binary formats
+            return Ok(());
+        }
+        let mut binary = vec![0u8; length as usize];
+        let mut written: GLsizei = 0;
+        let mut format: GLenum = 0;
+        unsafe {
+            gl.GetProgramBinary(program, length, &mut written, &mut format, binary.as_mut_ptr() as *mut _);
+        }
+        binary.truncate(written.max(0) as usize);
+
+        let mut data = Vec::with_capacity(binary.len() + 8);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&format.to_le_bytes());
+        data.extend_from_slice(&binary);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write to the side first so a crash never leaves half an entry.
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, &data)?;
+        fs::rename(&temp_path, &self.path)?;
+        self.evict_older();
+        Ok(())
+    }
+
+    /// Removes the other entries of this program variant.
+    fn evict_older(&self) {
+        let (dir, file_name) = match (self.path.parent(), self.path.file_name()) {
+            (Some(dir), Some(file_name)) => (dir, file_name),
+            _ => return,
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let older = name != file_name && name.to_str().map_or(false, |name| {
+                name.starts_with(&self.variant) && name.ends_with(".bin")
+            });
+            if older {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+fn gl_string(gl: &gl::Gl, name: GLenum) -> String {
+    unsafe {
+        let string = gl.GetString(name);
+        if string.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(string as *const _).to_string_lossy().into_owned()
+    }
+}
+
+/// 64 bit FNV-1a, stable across runs and platforms unlike the std hasher.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write(bytes);
+        hash.finish()
+    }
+
+    #[test]
+    fn fnv1a_matches_known_vectors() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn storing_an_entry_evicts_older_entries_of_the_variant() {
+        let dir = std::env::temp_dir().join(format!("rustgl-binary-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let older = BinaryCache::at(&dir, 1, 1);
+        let other_variant = BinaryCache::at(&dir, 2, 1);
+        let newer = BinaryCache::at(&dir, 1, 2);
+        for cache in &[&older, &other_variant, &newer] {
+            fs::write(&cache.path, MAGIC).unwrap();
+        }
+
+        newer.evict_older();
+        assert!(!older.path.exists());
+        assert!(other_variant.path.exists());
+        assert!(newer.path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
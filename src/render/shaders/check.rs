@@ -0,0 +1,779 @@
+//! Checks the stages of a program without a GL context.
+//!
+//! The sources are lexed after includes are expanded, nothing is compiled and
+//! `#if` blocks are not evaluated, so this only catches what can be seen on
+//! the surface:
+//!
+//! - a missing or unknown `#version` directive, or stages that disagree on it
+//! - numeric literals the driver would reject, such as `0f`
+//! - interface blocks and loose `in` variables that don't match the outputs of
+//!   the previous stage by name and type
+//!
+//! Variables are matched by name only, `layout(location = N)` is ignored.
+
+use super::diagnostics::{self, Diagnostic, Severity, ShaderStage};
+use super::preprocessor::SourceMap;
+use super::{Error, Program, Stage};
+use crate::resources::Resources;
+
+/// The outcome of checking a program.
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+    sources: SourceMap,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// The diagnostics rendered rustc-style, with the offending lines quoted.
+    pub fn render(&self) -> String {
+        diagnostics::render(&self.diagnostics, Some(&self.sources))
+    }
+}
+
+/// Checks the program made of the stage files of `name`, in the same way
+/// `Program::from_res_with_defines` would find them. Errors are only returned
+/// when the sources can't be loaded at all.
+pub fn check_program(resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Report, Error> {
+    let stages = Program::stages_from_res(resources, name, defines)?;
+    let mut checked = vec!();
+    let mut diagnostics = vec!();
+    for stage in &stages {
+        let source = stage.preprocessed.source.to_string_lossy();
+        let mut unit = Unit::new(stage);
+        let tokens = unit.lex(&source);
+        unit.check_version(&tokens);
+        unit.find_interface(&tokens);
+        diagnostics.append(&mut unit.diagnostics);
+        checked.push(unit.summary);
+    }
+
+    let versions = checked.iter()
+        .filter_map(|stage| stage.version.as_ref().map(|version| (stage, version)))
+        .collect::<Vec<_>>();
+    if let Some(&(first, version)) = versions.first() {
+        for &(stage, other) in &versions[1..] {
+            if other.number != version.number {
+                diagnostics.push(stage.diagnostic(
+                    Severity::Warning,
+                    &other.position,
+                    format!("#version {} differs from #version {} in the {} stage", other.number, version.number, first.stage_name()),
+                ));
+            }
+        }
+    }
+
+    for pair in checked.windows(2) {
+        match_interfaces(&pair[0], &pair[1], &mut diagnostics);
+    }
+
+    let mut files: Vec<(String, String)> = vec!();
+    for stage in &stages {
+        let source_map = &stage.preprocessed.source_map;
+        for file in source_map.files() {
+            if !files.iter().any(|(name, _)| name == file) {
+                let text = source_map.source(file).unwrap_or("");
+                files.push((file.clone(), text.into()));
+            }
+        }
+    }
+    Ok(Report {
+        diagnostics,
+        sources: SourceMap::from_files(files),
+    })
+}
+
+const KNOWN_VERSIONS: [u32; 16] = [110, 120, 130, 140, 150, 300, 310, 320, 330, 400, 410, 420, 430, 440, 450, 460];
+
+/// Qualifiers that may come before `in` or `out` and don't change whether
+/// two declarations match.
+const QUALIFIERS: [&str; 11] = [
+    "flat", "smooth", "noperspective", "centroid", "sample", "patch", "invariant", "precise",
+    "highp", "mediump", "lowp",
+];
+
+#[derive(Clone, Debug)]
+struct Position {
+    source_string: usize,
+    line: u32,
+    column: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum TokenKind {
+    Identifier,
+    Number,
+    Punctuation,
+    /// A whole preprocessor line, without the `#`.
+    Directive,
+}
+
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    position: Position,
+}
+
+impl<'a> Token<'a> {
+    fn is(&self, text: &str) -> bool {
+        self.kind != TokenKind::Directive && self.text == text
+    }
+}
+
+struct Version {
+    number: u32,
+    position: Position,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Direction {
+    In,
+    Out,
+}
+
+struct Variable {
+    name: String,
+    /// The type including any array size, e.g. `vec3[2]`.
+    ty: String,
+    position: Position,
+}
+
+/// An interface block or a loose variable, which is treated as a block
+/// without a name holding just that variable.
+struct Interface {
+    direction: Direction,
+    block: Option<String>,
+    members: Vec<Variable>,
+    position: Position,
+}
+
+/// What is left of a stage after checking it on its own.
+struct CheckedStage {
+    stage: Option<ShaderStage>,
+    files: Vec<String>,
+    version: Option<Version>,
+    interfaces: Vec<Interface>,
+}
+
+impl CheckedStage {
+    fn stage_name(&self) -> String {
+        self.stage.map_or_else(|| "unknown".into(), |stage| stage.to_string())
+    }
+
+    fn file(&self, position: &Position) -> String {
+        self.files.get(position.source_string)
+            .cloned()
+            .unwrap_or_else(|| position.source_string.to_string())
+    }
+
+    fn diagnostic(&self, severity: Severity, position: &Position, message: String) -> Diagnostic {
+        Diagnostic {
+            stage: self.stage,
+            file: Some(self.file(position)),
+            line: Some(position.line),
+            column: Some(position.column),
+            severity,
+            message,
+        }
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = &Interface> {
+        self.interfaces.iter().filter(|interface| interface.direction == Direction::In)
+    }
+
+    fn outputs(&self) -> impl Iterator<Item = &Interface> {
+        self.interfaces.iter().filter(|interface| interface.direction == Direction::Out)
+    }
+}
+
+/// A single stage being checked.
+struct Unit {
+    summary: CheckedStage,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Unit {
+    fn new(stage: &Stage) -> Unit {
+        Unit {
+            summary: CheckedStage {
+                stage: ShaderStage::from_gl(stage.kind),
+                files: stage.preprocessed.source_map.files().to_vec(),
+                version: None,
+                interfaces: vec!(),
+            },
+            diagnostics: vec!(),
+        }
+    }
+
+    fn error(&mut self, position: &Position, message: String) {
+        let diagnostic = self.summary.diagnostic(Severity::Error, position, message);
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Splits `source` into tokens, following the `#line` directives inserted
+    /// by the preprocessor so positions point into the original files.
+    fn lex<'a>(&mut self, source: &'a str) -> Vec<Token<'a>> {
+        let bytes = source.as_bytes();
+        let mut tokens = vec!();
+        let mut position = Position { source_string: 0, line: 1, column: 1 };
+        let mut line_start = 0;
+        let mut at_line_start = true;
+        let mut next_line: Option<(u32, Option<usize>)> = None;
+        let mut i = 0;
+        while i < bytes.len() {
+            let c = bytes[i];
+            position.column = (i - line_start + 1) as u32;
+            if c == b'\n' {
+                match next_line.take() {
+                    Some((line, source_string)) => {
+                        position.line = line;
+                        position.source_string = source_string.unwrap_or(position.source_string);
+                    },
+                    None => position.line += 1,
+                }
+                i += 1;
+                line_start = i;
+                at_line_start = true;
+                continue;
+            }
+            if c.is_ascii_whitespace() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            if c == b'#' && at_line_start {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                let text = source[start + 1..i].trim();
+                if let Some(("line", arguments)) = split_directive(text) {
+                    let mut arguments = arguments.split_whitespace().map(str::parse);
+                    if let Some(Ok(line)) = arguments.next() {
+                        next_line = Some((line as u32, arguments.next().and_then(Result::ok)));
+                    }
+                }
+                tokens.push(Token { kind: TokenKind::Directive, text, position: position.clone() });
+                continue;
+            }
+            at_line_start = false;
+            if bytes[i..].starts_with(b"//") {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            } else if bytes[i..].starts_with(b"/*") {
+                let comment_start = position.clone();
+                i += 2;
+                loop {
+                    if i >= bytes.len() {
+                        self.error(&comment_start, "unterminated block comment".into());
+                        break;
+                    }
+                    if bytes[i..].starts_with(b"*/") {
+                        i += 2;
+                        break;
+                    }
+                    if bytes[i] == b'\n' {
+                        position.line += 1;
+                        line_start = i + 1;
+                    }
+                    i += 1;
+                }
+            } else if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).map_or(false, u8::is_ascii_digit)) {
+                let hex = bytes[i..].starts_with(b"0x") || bytes[i..].starts_with(b"0X");
+                while i < bytes.len() {
+                    let c = bytes[i];
+                    let exponent_sign = (c == b'+' || c == b'-') && !hex && (bytes[i - 1] == b'e' || bytes[i - 1] == b'E');
+                    if !(c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || exponent_sign) {
+                        break;
+                    }
+                    i += 1;
+                }
+                let text = &source[start..i];
+                if let Err(message) = check_number(text) {
+                    self.error(&position, message);
+                }
+                tokens.push(Token { kind: TokenKind::Number, text, position: position.clone() });
+            } else if c.is_ascii_alphabetic() || c == b'_' {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push(Token { kind: TokenKind::Identifier, text: &source[start..i], position: position.clone() });
+            } else {
+                i += source[i..].chars().next().map_or(1, char::len_utf8);
+                tokens.push(Token { kind: TokenKind::Punctuation, text: &source[start..i], position: position.clone() });
+            }
+        }
+        tokens
+    }
+
+    fn check_version(&mut self, tokens: &[Token]) {
+        let first = match tokens.first() {
+            Some(first) => first,
+            None => return,
+        };
+        let version = tokens.iter()
+            .find(|token| token.kind == TokenKind::Directive && split_directive(token.text).map_or(false, |(name, _)| name == "version"));
+        let version = match version {
+            Some(version) => version,
+            None => {
+                self.error(&first.position, "missing #version directive, the driver would assume GLSL 1.10".into());
+                return;
+            },
+        };
+        if !std::ptr::eq(version, first) {
+            self.error(&version.position, "#version must come before anything else but comments".into());
+        }
+        let arguments = split_directive(version.text).map_or("", |(_, arguments)| arguments);
+        let mut arguments = arguments.split_whitespace();
+        let number = match arguments.next().map(str::parse::<u32>) {
+            Some(Ok(number)) if KNOWN_VERSIONS.contains(&number) => number,
+            Some(_) => {
+                self.error(&version.position, format!("unknown GLSL version in `#{}`", version.text));
+                return;
+            },
+            None => {
+                self.error(&version.position, "#version is missing the version number".into());
+                return;
+            },
+        };
+        let es = number == 300 || number == 310 || number == 320;
+        match arguments.next() {
+            None if es => self.error(&version.position, format!("GLSL ES {} requires the `es` profile", number)),
+            Some("es") if !es => self.error(&version.position, format!("GLSL {} has no `es` profile", number)),
+            Some("core") | Some("compatibility") if number < 150 => {
+                self.error(&version.position, format!("GLSL {} has no profiles, they were added in 150", number));
+            },
+            Some(profile) if !["core", "compatibility", "es"].contains(&profile) => {
+                self.error(&version.position, format!("unknown profile `{}`, expected `core` or `compatibility`", profile));
+            },
+            _ => {},
+        }
+        if let Some(extra) = arguments.next() {
+            self.error(&version.position, format!("unexpected `{}` after the #version profile", extra));
+        }
+        self.summary.version = Some(Version {
+            number,
+            position: version.position.clone(),
+        });
+    }
+
+    /// Collects the `in` and `out` declarations at the top level.
+    fn find_interface(&mut self, tokens: &[Token]) {
+        let tokens = tokens.iter()
+            .filter(|token| token.kind != TokenKind::Directive)
+            .collect::<Vec<_>>();
+        let mut i = 0;
+        while i < tokens.len() {
+            let mut next = i;
+            if tokens[next].is("layout") {
+                next = skip_group(&tokens, next + 1, "(", ")");
+            }
+            while next < tokens.len() && QUALIFIERS.iter().any(|&qualifier| tokens[next].is(qualifier)) {
+                next += 1;
+            }
+            let direction = match tokens.get(next) {
+                Some(token) if token.is("in") => Some(Direction::In),
+                Some(token) if token.is("out") => Some(Direction::Out),
+                _ => None,
+            };
+            match direction {
+                Some(direction) => i = self.parse_interface(&tokens, next + 1, direction),
+                None => i = skip_declaration(&tokens, i),
+            }
+        }
+    }
+
+    /// Parses what follows `in` or `out`, returns the index after it.
+    fn parse_interface(&mut self, tokens: &[&Token], start: usize, direction: Direction) -> usize {
+        // `layout(triangles) in;` and the like only set up the stage
+        if tokens.get(start).map_or(false, |token| token.is(";")) {
+            return start + 1;
+        }
+        let is_block = tokens.get(start + 1).map_or(false, |token| token.is("{"));
+        if is_block {
+            let block = tokens[start];
+            let mut members = vec!();
+            let mut i = start + 2;
+            while i < tokens.len() && !tokens[i].is("}") {
+                while i < tokens.len() && QUALIFIERS.iter().any(|&qualifier| tokens[i].is(qualifier)) {
+                    i += 1;
+                }
+                if tokens.get(i).map_or(false, |token| token.is("layout")) {
+                    i = skip_group(tokens, i + 1, "(", ")");
+                }
+                let (mut variables, end) = self.parse_variables(tokens, i);
+                members.append(&mut variables);
+                i = end;
+            }
+            // The instance name and its array size, per-vertex arrays of the
+            // geometry and tessellation stages make the latter differ.
+            let end = skip_declaration(tokens, i);
+            self.summary.interfaces.push(Interface {
+                direction,
+                block: Some(block.text.into()),
+                members,
+                position: block.position.clone(),
+            });
+            end
+        } else {
+            let (variables, end) = self.parse_variables(tokens, start);
+            for mut variable in variables {
+                // Loose per-vertex inputs are arrays in some stages, their
+                // size doesn't have to match.
+                if let Some(bracket) = variable.ty.find('[') {
+                    variable.ty.truncate(bracket);
+                }
+                self.summary.interfaces.push(Interface {
+                    direction,
+                    block: None,
+                    position: variable.position.clone(),
+                    members: vec![variable],
+                });
+            }
+            end
+        }
+    }
+
+    /// Parses `type name[size], other;` and returns the variables and the
+    /// index after the semicolon.
+    fn parse_variables(&mut self, tokens: &[&Token], start: usize) -> (Vec<Variable>, usize) {
+        let mut variables = vec!();
+        let ty = match tokens.get(start) {
+            Some(token) if token.kind == TokenKind::Identifier => token.text,
+            Some(token) => {
+                self.error(&token.position, format!("expected a type, found `{}`", token.text));
+                return (variables, skip_declaration(tokens, start));
+            },
+            None => return (variables, start),
+        };
+        let mut i = start + 1;
+        let type_array = array_suffix(tokens, &mut i);
+        while let Some(token) = tokens.get(i) {
+            if token.kind != TokenKind::Identifier {
+                self.error(&token.position, format!("expected a name, found `{}`", token.text));
+                return (variables, skip_declaration(tokens, i));
+            }
+            i += 1;
+            let array = array_suffix(tokens, &mut i);
+            variables.push(Variable {
+                name: token.text.into(),
+                ty: format!("{}{}{}", ty, type_array, array),
+                position: token.position.clone(),
+            });
+            match tokens.get(i) {
+                Some(separator) if separator.is(",") => i += 1,
+                Some(end) if end.is(";") => return (variables, i + 1),
+                Some(other) => {
+                    self.error(&other.position, format!("expected `;`, found `{}`", other.text));
+                    return (variables, skip_declaration(tokens, i));
+                },
+                None => break,
+            }
+        }
+        (variables, i)
+    }
+}
+
+/// Checks that every input of `consumer` is written by `producer`.
+fn match_interfaces(producer: &CheckedStage, consumer: &CheckedStage, diagnostics: &mut Vec<Diagnostic>) {
+    let producer_name = producer.stage_name();
+    for input in consumer.inputs() {
+        let output = producer.outputs().find(|output| match (&output.block, &input.block) {
+            (Some(output), Some(input)) => output == input,
+            (None, None) => output.members[0].name == input.members[0].name,
+            _ => false,
+        });
+        let output = match output {
+            Some(output) => output,
+            None => {
+                if input.block.is_none() && input.members[0].name.starts_with("gl_") {
+                    continue;
+                }
+                let what = match input.block {
+                    Some(ref block) => format!("input block `{}`", block),
+                    None => format!("input `{}`", input.members[0].name),
+                };
+                diagnostics.push(consumer.diagnostic(
+                    Severity::Error,
+                    &input.position,
+                    format!("{} is not written by the {} stage", what, producer_name),
+                ));
+                continue;
+            },
+        };
+        let output_location = format!("{}:{}", producer.file(&output.position), output.position.line);
+        for member in &input.members {
+            match output.members.iter().find(|output| output.name == member.name) {
+                Some(written) if written.ty != member.ty => diagnostics.push(consumer.diagnostic(
+                    Severity::Error,
+                    &member.position,
+                    format!(
+                        "`{}` is a `{}` here but a `{}` in the {} stage ({})",
+                        member.name, member.ty, written.ty, producer_name, output_location,
+                    ),
+                )),
+                Some(_) => {},
+                None => diagnostics.push(consumer.diagnostic(
+                    Severity::Error,
+                    &member.position,
+                    format!(
+                        "`{}` is missing from the matching block in the {} stage ({})",
+                        member.name, producer_name, output_location,
+                    ),
+                )),
+            }
+        }
+        // Blocks have to match member for member, not just cover the inputs.
+        if let Some(ref block) = input.block {
+            for written in &output.members {
+                if !input.members.iter().any(|member| member.name == written.name) {
+                    diagnostics.push(consumer.diagnostic(
+                        Severity::Error,
+                        &input.position,
+                        format!(
+                            "block `{}` is missing `{} {}` written by the {} stage ({})",
+                            block, written.ty, written.name, producer_name, output_location,
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn split_directive(text: &str) -> Option<(&str, &str)> {
+    let name_len = text.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(text.len());
+    if name_len == 0 {
+        return None;
+    }
+    Some((&text[..name_len], text[name_len..].trim()))
+}
+
+/// Checks a numeric literal against the GLSL grammar.
+fn check_number(text: &str) -> Result<(), String> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        let digits = hex.trim_end_matches('u');
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()) && lower.len() - 2 - digits.len() <= 1 {
+            return Ok(());
+        }
+        return Err(format!("malformed hexadecimal literal `{}`", text));
+    }
+
+    let is_float = lower.contains('.') || lower.contains('e');
+    if !is_float {
+        let digits = lower.trim_end_matches('u');
+        let suffix_len = lower.len() - digits.len();
+        if digits.chars().all(|c| c.is_ascii_digit()) && suffix_len <= 1 {
+            if digits.len() > 1 && digits.starts_with('0') && !digits.chars().all(|c| c < '8') {
+                return Err(format!("`{}` starts with 0 and is read as octal, but has digits above 7", text));
+            }
+            return Ok(());
+        }
+        let digits = float_body(&lower);
+        if digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!(
+                "`{}` is not a valid literal, floating point literals need a decimal point or an exponent, e.g. `{}.0`",
+                text, digits,
+            ));
+        }
+        return Err(format!("malformed numeric literal `{}`", text));
+    }
+
+    let body = float_body(&lower);
+    let (mantissa, exponent) = match body.find('e') {
+        Some(e) => (&body[..e], Some(&body[e + 1..])),
+        None => (body, None),
+    };
+    let mut parts = mantissa.splitn(2, '.');
+    let whole = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    let mantissa_valid = !(whole.is_empty() && fraction.is_empty())
+        && whole.chars().all(|c| c.is_ascii_digit())
+        && fraction.chars().all(|c| c.is_ascii_digit());
+    let exponent_valid = exponent.map_or(true, |exponent| {
+        let digits = exponent.trim_start_matches(&['+', '-'][..]);
+        exponent.len() - digits.len() <= 1 && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    });
+    if mantissa_valid && exponent_valid {
+        Ok(())
+    } else {
+        Err(format!("malformed numeric literal `{}`", text))
+    }
+}
+
+/// A lowercased floating point literal without its `f` or `lf` suffix, at
+/// most one of which is stripped.
+fn float_body(lower: &str) -> &str {
+    lower.strip_suffix("lf")
+        .or_else(|| lower.strip_suffix('f'))
+        .unwrap_or(lower)
+}
+
+/// Collects `[N]` suffixes starting at `i` and moves past them.
+fn array_suffix(tokens: &[&Token], i: &mut usize) -> String {
+    let mut suffix = String::new();
+    while tokens.get(*i).map_or(false, |token| token.is("[")) {
+        let end = skip_group(tokens, *i, "[", "]");
+        for token in &tokens[*i..end] {
+            suffix.push_str(token.text);
+        }
+        *i = end;
+    }
+    suffix
+}
+
+/// Skips a balanced group starting at `open`, returns the index after the
+/// closing token. Does nothing if there is no group at `start`.
+fn skip_group(tokens: &[&Token], start: usize, open: &str, close: &str) -> usize {
+    if !tokens.get(start).map_or(false, |token| token.is(open)) {
+        return start;
+    }
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        if token.is(open) {
+            depth += 1;
+        } else if token.is(close) {
+            depth -= 1;
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+    }
+    tokens.len()
+}
+
+/// Skips to the end of the declaration or function definition starting at
+/// `start`, returns the index after it.
+fn skip_declaration(tokens: &[&Token], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token.text {
+            "(" | "[" => depth += 1,
+            ")" | "]" => depth -= 1,
+            "{" => {
+                // A function body ends at its closing brace, blocks and
+                // structs carry on to the semicolon.
+                let is_function = depth == 0 && i > start && tokens[i - 1].is(")");
+                if is_function {
+                    return skip_group(tokens, i, "{", "}");
+                }
+                depth += 1;
+            },
+            "}" => depth -= 1,
+            ";" if depth <= 0 => return i + 1,
+            _ => {},
+        }
+    }
+    tokens.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(stage: ShaderStage) -> Unit {
+        Unit {
+            summary: CheckedStage {
+                stage: Some(stage),
+                files: vec!["test.glsl".into()],
+                version: None,
+                interfaces: vec!(),
+            },
+            diagnostics: vec!(),
+        }
+    }
+
+    fn check(stage: ShaderStage, source: &str) -> (CheckedStage, Vec<Diagnostic>) {
+        let mut unit = unit(stage);
+        let tokens = unit.lex(source);
+        unit.check_version(&tokens);
+        unit.find_interface(&tokens);
+        (unit.summary, unit.diagnostics)
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect()
+    }
+
+    fn interfaces(source: &str) -> Vec<Diagnostic> {
+        let (vertex, mut diagnostics) = check(ShaderStage::Vertex, &format!("#version 330 core\n{}", source));
+        let (fragment, mut fragment_diagnostics) = check(ShaderStage::Fragment, "#version 330 core\nin VS_OUTPUT {\n    vec2 texCoord;\n} IN;\nin float fade;\n");
+        diagnostics.append(&mut fragment_diagnostics);
+        match_interfaces(&vertex, &fragment, &mut diagnostics);
+        diagnostics
+    }
+
+    #[test]
+    fn lexes_tokens_with_positions() {
+        let mut unit = unit(ShaderStage::Vertex);
+        let tokens = unit.lex("#version 330\nvec2 a = b.xy;\n/* multi\nline */ c");
+        let texts = tokens.iter().map(|token| token.text).collect::<Vec<_>>();
+        assert_eq!(texts, ["version 330", "vec2", "a", "=", "b", ".", "xy", ";", "c"]);
+        assert_eq!(tokens[0].kind, TokenKind::Directive);
+        assert_eq!(tokens[2].kind, TokenKind::Identifier);
+        assert_eq!((tokens[2].position.line, tokens[2].position.column), (2, 6));
+        assert_eq!((tokens[8].position.line, tokens[8].position.column), (4, 9));
+    }
+
+    #[test]
+    fn follows_line_directives() {
+        let (_, diagnostics) = check(ShaderStage::Fragment, "#version 330\n#line 40 1\nfloat a = 0f;\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (Some(40), Some(11)));
+    }
+
+    #[test]
+    fn skips_non_ascii_comments() {
+        let source = "#version 330\n/* temperature in \u{b0}C */\n// \u{fc}ber\nfloat a = 1.0; /* \u{2192} */\n";
+        let (_, diagnostics) = check(ShaderStage::Fragment, source);
+        assert!(diagnostics.is_empty(), "{:?}", messages(&diagnostics));
+    }
+
+    #[test]
+    fn reports_unterminated_comments() {
+        let (_, diagnostics) = check(ShaderStage::Fragment, "#version 330\n/* \u{b0}");
+        assert_eq!(messages(&diagnostics), ["unterminated block comment"]);
+    }
+
+    #[test]
+    fn checks_versions() {
+        assert_eq!(messages(&check(ShaderStage::Vertex, "void main() {}").1), ["missing #version directive, the driver would assume GLSL 1.10"]);
+        assert_eq!(messages(&check(ShaderStage::Vertex, "#version 331\n").1), ["unknown GLSL version in `#version 331`"]);
+        assert_eq!(messages(&check(ShaderStage::Vertex, "#version 300\n").1), ["GLSL ES 300 requires the `es` profile"]);
+        assert!(check(ShaderStage::Vertex, "// header\n#version 450 core\n").1.is_empty());
+    }
+
+    #[test]
+    fn rejects_float_literals_without_a_point() {
+        assert!(check_number("0f").unwrap_err().contains("e.g. `0.0`"));
+        assert!(check_number("12F").is_err());
+        assert!(check_number("09").is_err());
+        assert!(check_number("0x1g").is_err());
+        assert!(check_number("1.0.0").is_err());
+        for doubled in &["1.0ff", "1.0lff", "1.0flf", "1ff"] {
+            assert!(check_number(doubled).is_err(), "{} should be malformed", doubled);
+        }
+        for valid in &["0", "0u", "017", "0x1Fu", "1.0", "1.", ".5", "1e3", "1.5e-3f", "2.0lf", "0.0f"] {
+            assert!(check_number(valid).is_ok(), "{} should be valid", valid);
+        }
+    }
+
+    #[test]
+    fn matches_stage_interfaces() {
+        let diagnostics = interfaces("out VS_OUTPUT {\n    vec2 texCoord;\n} OUT;\nout float fade;\n");
+        assert!(diagnostics.is_empty(), "{:?}", messages(&diagnostics));
+    }
+
+    #[test]
+    fn reports_interface_mismatches() {
+        let diagnostics = interfaces("out VS_OUTPUT {\n    vec3 texCoord;\n    float extra;\n} OUT;\n");
+        assert_eq!(messages(&diagnostics), [
+            "`texCoord` is a `vec2` here but a `vec3` in the vertex stage (test.glsl:2)",
+            "block `VS_OUTPUT` is missing `float extra` written by the vertex stage (test.glsl:2)",
+            "input `fade` is not written by the vertex stage",
+        ]);
+    }
+}
@@ -0,0 +1,339 @@
+//! Turns driver info logs into structured diagnostics.
+//!
+//! Every driver formats its logs differently. The location prefixes
+//! understood here are
+//!
+//! ```text
+//! Mesa      0:12(5): error: message
+//! NVIDIA    0(12) : error C0000: message
+//! AMD       ERROR: 0:12: message
+//! ```
+//!
+//! where the first number is the source string, which `SourceMap` maps back
+//! to a file. Lines without a location start a new diagnostic if they begin
+//! with a severity and are appended to the previous one otherwise.
+
+use std::fmt;
+
+use super::preprocessor::SourceMap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    TessControl,
+    TessEvaluation,
+    Geometry,
+    Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+    pub fn from_gl(kind: gl::types::GLenum) -> Option<ShaderStage> {
+        match kind {
+            gl::VERTEX_SHADER => Some(ShaderStage::Vertex),
+            gl::TESS_CONTROL_SHADER => Some(ShaderStage::TessControl),
+            gl::TESS_EVALUATION_SHADER => Some(ShaderStage::TessEvaluation),
+            gl::GEOMETRY_SHADER => Some(ShaderStage::Geometry),
+            gl::FRAGMENT_SHADER => Some(ShaderStage::Fragment),
+            gl::COMPUTE_SHADER => Some(ShaderStage::Compute),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::TessControl => "tessellation control",
+            ShaderStage::TessEvaluation => "tessellation evaluation",
+            ShaderStage::Geometry => "geometry",
+            ShaderStage::Fragment => "fragment",
+            ShaderStage::Compute => "compute",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// The stage being compiled, `None` for link logs.
+    pub stage: Option<ShaderStage>,
+    /// The resource the diagnostic points into. Source string numbers that
+    /// can't be mapped are kept as they are.
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Parses an info log. `source_map` resolves source string numbers to files.
+pub fn parse_log(log: &str, stage: Option<ShaderStage>, source_map: Option<&SourceMap>) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = vec!();
+    for line in log.lines() {
+        let line = line.trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(location) = parse_location(line) {
+            let (severity, message) = parse_severity(location.rest.trim_start())
+                .unwrap_or((Severity::Error, location.rest.trim()));
+            let file = source_map
+                .and_then(|source_map| source_map.file(location.source_string as usize))
+                .map(String::from)
+                .unwrap_or_else(|| location.source_string.to_string());
+            diagnostics.push(Diagnostic {
+                stage,
+                file: Some(file),
+                line: Some(location.line),
+                column: location.column,
+                severity: location.severity.unwrap_or(severity),
+                message: message.into(),
+            });
+        } else if let Some((severity, message)) = parse_severity(line.trim_start()) {
+            // AMD closes every failed log with a count of the errors above
+            if message.ends_with("compilation errors.  No code generated.") {
+                continue;
+            }
+            diagnostics.push(Diagnostic {
+                stage,
+                file: None,
+                line: None,
+                column: None,
+                severity,
+                message: message.into(),
+            });
+        } else if let Some(previous) = diagnostics.last_mut() {
+            previous.message.push('\n');
+            previous.message.push_str(line.trim());
+        } else {
+            diagnostics.push(Diagnostic {
+                stage,
+                file: None,
+                line: None,
+                column: None,
+                severity: Severity::Error,
+                message: line.trim().into(),
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Renders diagnostics the way rustc does, with the offending line quoted
+/// when `source_map` has the source of the file.
+pub fn render(diagnostics: &[Diagnostic], source_map: Option<&SourceMap>) -> String {
+    diagnostics.iter()
+        .map(|diagnostic| {
+            let source = diagnostic.file.as_ref()
+                .and_then(|file| source_map.and_then(|source_map| source_map.source(file)));
+            diagnostic.render(source)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Diagnostic {
+    /// Renders this diagnostic, `source` is the text of `file`.
+    pub fn render(&self, source: Option<&str>) -> String {
+        let mut lines = vec![format!("{}: {}", self.severity, self.message)];
+        let location = match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => Some(format!("{}:{}:{}", file, line, column)),
+            (Some(file), Some(line), None) => Some(format!("{}:{}", file, line)),
+            (Some(file), None, _) => Some(file.clone()),
+            (None, ..) => None,
+        };
+        let location = match (location, self.stage) {
+            (Some(location), Some(stage)) => Some(format!("{} ({} shader)", location, stage)),
+            (Some(location), None) => Some(location),
+            (None, Some(stage)) => Some(format!("{} shader", stage)),
+            (None, None) => None,
+        };
+        let quoted = self.line.and_then(|line| {
+            source.and_then(|source| source.lines().nth((line as usize).checked_sub(1)?))
+        });
+        let gutter = " ".repeat(self.line.map_or(1, |line| line.to_string().len()));
+        if let Some(location) = location {
+            lines.push(format!("{}--> {}", gutter, location));
+        }
+        if let (Some(quoted), Some(line)) = (quoted, self.line) {
+            lines.push(format!("{} |", gutter));
+            lines.push(format!("{} | {}", line, quoted));
+            if let Some(column) = self.column.filter(|&column| column > 0) {
+                lines.push(format!("{} | {}^", gutter, " ".repeat(column as usize - 1)));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+struct Location<'a> {
+    source_string: u32,
+    line: u32,
+    column: Option<u32>,
+    /// AMD puts the severity in front of the location.
+    severity: Option<Severity>,
+    rest: &'a str,
+}
+
+fn parse_location(line: &str) -> Option<Location<'_>> {
+    let (severity, line) = if let Some(line) = line.strip_prefix("ERROR: ") {
+        (Some(Severity::Error), line)
+    } else if let Some(line) = line.strip_prefix("WARNING: ") {
+        (Some(Severity::Warning), line)
+    } else {
+        (None, line)
+    };
+    let (source_string, rest) = parse_number(line)?;
+    if let Some(rest) = rest.strip_prefix(':') {
+        // Mesa `0:12(5):` or AMD `0:12:`
+        let (line_number, rest) = parse_number(rest)?;
+        let (column, rest) = if let Some(rest) = rest.strip_prefix('(') {
+            let (column, rest) = parse_number(rest)?;
+            (Some(column), rest.strip_prefix(')')?)
+        } else {
+            (None, rest)
+        };
+        Some(Location {
+            source_string,
+            line: line_number,
+            column,
+            severity,
+            rest: rest.strip_prefix(':')?,
+        })
+    } else if let Some(rest) = rest.strip_prefix('(') {
+        // NVIDIA `0(12) :`
+        let (line_number, rest) = parse_number(rest)?;
+        let rest = rest.strip_prefix(')')?.trim_start().strip_prefix(':')?;
+        Some(Location {
+            source_string,
+            line: line_number,
+            column: None,
+            severity,
+            rest,
+        })
+    } else {
+        None
+    }
+}
+
+fn parse_number(text: &str) -> Option<(u32, &str)> {
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    let number = text[..digits].parse().ok()?;
+    Some((number, &text[digits..]))
+}
+
+/// Splits `error: message`, `warning C7050: message` and the like into the
+/// severity and the message.
+fn parse_severity(text: &str) -> Option<(Severity, &str)> {
+    let lower = text.to_lowercase();
+    let (severity, word) = [(Severity::Error, "error"), (Severity::Warning, "warning"), (Severity::Note, "note")].iter()
+        .cloned()
+        .find(|&(_, word)| lower.starts_with(word))?;
+    let rest = &text[word.len()..];
+    // an optional code such as NVIDIA's C0000
+    let colon = rest.find(':')?;
+    if rest[..colon].trim().contains(' ') {
+        return None;
+    }
+    Some((severity, rest[colon + 1..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(diagnostic: &Diagnostic) -> (Option<&str>, Option<u32>, Option<u32>) {
+        (diagnostic.file.as_deref(), diagnostic.line, diagnostic.column)
+    }
+
+    #[test]
+    fn parses_mesa_logs() {
+        let log = "0:11(28): error: syntax error, unexpected NEW_IDENTIFIER, expecting ')' or ','\n\
+                   0:5(10): warning: `tint' used uninitialized\n\0";
+        let diagnostics = parse_log(log, Some(ShaderStage::Fragment), None);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(location(&diagnostics[0]), (Some("0"), Some(11), Some(28)));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].message, "syntax error, unexpected NEW_IDENTIFIER, expecting ')' or ','");
+        assert_eq!(diagnostics[0].stage, Some(ShaderStage::Fragment));
+        assert_eq!(location(&diagnostics[1]), (Some("0"), Some(5), Some(10)));
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn parses_nvidia_logs() {
+        let log = "0(11) : error C0000: syntax error, unexpected identifier, expecting \",\" or \")\" at token \"f\"\n\
+                   0(7) : warning C7533: global variable gl_FragColor is deprecated after version 120\n";
+        let diagnostics = parse_log(log, Some(ShaderStage::Fragment), None);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(location(&diagnostics[0]), (Some("0"), Some(11), None));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].message, "syntax error, unexpected identifier, expecting \",\" or \")\" at token \"f\"");
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].message, "global variable gl_FragColor is deprecated after version 120");
+    }
+
+    #[test]
+    fn parses_amd_logs() {
+        let log = "ERROR: 0:11: 'f' : syntax error syntax error\n\
+                   WARNING: 0:3: 'unused' : variable is never read\n\
+                   ERROR: 1 compilation errors.  No code generated.\n\n";
+        let diagnostics = parse_log(log, Some(ShaderStage::Vertex), None);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(location(&diagnostics[0]), (Some("0"), Some(11), None));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].message, "'f' : syntax error syntax error");
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn parses_link_logs_without_locations() {
+        let log = "error: fragment shader input `IN' has no matching output in the previous stage\n";
+        let diagnostics = parse_log(log, None, None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(location(&diagnostics[0]), (None, None, None));
+        assert_eq!(diagnostics[0].message, "fragment shader input `IN' has no matching output in the previous stage");
+
+        let log = "Vertex info\n-----------\n0(3) : error C5145: must write to gl_Position\n";
+        let diagnostics = parse_log(log, None, None);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].message, "Vertex info\n-----------");
+        assert_eq!(location(&diagnostics[1]), (Some("0"), Some(3), None));
+    }
+
+    #[test]
+    fn maps_source_strings_to_files() {
+        let source_map = SourceMap::from_files(vec![
+            ("shaders/lit.frag".into(), "#version 330 core\n#include \"common/light.glsl\"\n".into()),
+            ("shaders/common/light.glsl".into(), "float light() {\n    return 1.0f;\n}\n".into()),
+        ]);
+        let diagnostics = parse_log("1:2(12): error: unexpected NEW_IDENTIFIER\n", Some(ShaderStage::Fragment), Some(&source_map));
+        assert_eq!(location(&diagnostics[0]), (Some("shaders/common/light.glsl"), Some(2), Some(12)));
+        assert_eq!(
+            render(&diagnostics, Some(&source_map)),
+            "error: unexpected NEW_IDENTIFIER\n \
+             --> shaders/common/light.glsl:2:12 (fragment shader)\n  \
+             |\n\
+             2 |     return 1.0f;\n  \
+             |            ^"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! Resolves `#include "file"` directives in GLSL sources.
+//!
+//! Included names are relative to the directory of the file including them
+//! and are loaded through `Resources`. Every file is given a source string
+//! number and `#line` directives are inserted around each include, so the
+//! driver reports errors against the original file and line. `SourceMap`
+//! turns those numbers back into resource names and keeps the text of each
+//! file for quoting in diagnostics.
+//!
+//! Defines passed to `preprocess` are inserted right after the `#version`
+//! line, followed by a `#line` directive so line numbers stay intact.
+//!
+//! Includes are expanded wherever they appear, inactive `#if` branches and
+//! comments are not looked at. Use include guards for files that may end up
+//! included twice.
+
+use std::ffi::CString;
+
+use crate::resources::{self, Resources};
+use super::Error;
+
+/// A fully expanded shader source.
+pub struct Preprocessed {
+    pub source: CString,
+    pub source_map: SourceMap,
+}
+
+/// The resources a preprocessed source was put together from, indexed by the
+/// source string number used in its `#line` directives.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<String>,
+    sources: Vec<String>,
+}
+
+impl SourceMap {
+    /// The resource name of every file, the root file first.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn file(&self, source_string: usize) -> Option<&str> {
+        self.files.get(source_string).map(|file| file.as_str())
+    }
+
+    /// The text of a file as it was loaded, before includes were expanded.
+    pub fn source(&self, file: &str) -> Option<&str> {
+        self.files.iter()
+            .position(|name| name == file)
+            .map(|index| self.sources[index].as_str())
+    }
+
+    /// A source map for files that were put together by other means, as
+    /// resource name and text pairs.
+    pub fn from_files(files: Vec<(String, String)>) -> SourceMap {
+        let (files, sources) = files.into_iter().unzip();
+        SourceMap {
+            files,
+            sources,
+        }
+    }
+}
+
+/// Loads `name`, expands every include in it and defines each of `defines`
+/// as a macro.
+pub fn preprocess(resources: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Preprocessed, Error> {
+    for (index, &(define, value)) in defines.iter().enumerate() {
+        if defines[..index].iter().any(|&(earlier, _)| earlier == define) {
+            return Err(Error::DuplicateDefine {
+                name: name.into(),
+                define: define.into(),
+            });
+        }
+        let valid_name = define.chars().next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+            && define.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name || value.contains('\n') {
+            return Err(Error::InvalidDefine {
+                name: name.into(),
+                define: format!("{}={}", define, value),
+            });
+        }
+    }
+    let mut preprocessor = Preprocessor {
+        resources,
+        defines: defines.iter()
+            .map(|&(define, value)| format!("#define {} {}\n", define, value))
+            .collect(),
+        output: String::new(),
+        source_map: SourceMap::default(),
+        stack: vec!(),
+    };
+    preprocessor.expand(name)?;
+    Ok(Preprocessed {
+        source: CString::new(preprocessor.output).expect("load_cstring rejects nul bytes"),
+        source_map: preprocessor.source_map,
+    })
+}
+
+struct Preprocessor<'a> {
+    resources: &'a Resources,
+    /// `#define` lines for the root file.
+    defines: String,
+    output: String,
+    source_map: SourceMap,
+    /// The files currently being expanded, for cycle detection.
+    stack: Vec<String>,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn expand(&mut self, name: &str) -> Result<(), Error> {
+        if self.stack.iter().any(|file| file == name) {
+            let mut chain = self.stack.clone();
+            chain.push(name.into());
+            return Err(Error::IncludeCycle {
+                name: self.stack[0].clone(),
+                chain: chain.join(" -> "),
+            });
+        }
+        let source = self.resources.load_cstring(name).map_err(|inner| Error::ResourceLoad {
+            name: name.into(),
+            inner,
+        })?;
+        let source = source.to_string_lossy();
+
+        let source_string = self.source_map.files.len();
+        self.source_map.files.push(name.into());
+        self.source_map.sources.push(source.clone().into_owned());
+        self.stack.push(name.into());
+        let is_root = self.stack.len() == 1;
+        if !is_root {
+            self.output.push_str(&format!("#line 1 {}\n", source_string));
+        } else if !self.defines.is_empty() && !source.lines().any(|line| is_directive(line, "version")) {
+            self.output.push_str(&self.defines);
+            self.output.push_str("#line 1 0\n");
+        }
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let directive = parse_directive(line);
+            match directive {
+                Some(("include", argument)) => {
+                    let included = self.include_name(name, line_number, argument)?;
+                    self.expand(&included)?;
+                    self.output.push_str(&format!("#line {} {}\n", line_number + 1, source_string));
+                },
+                Some(("version", _)) if !is_root => {
+                    return Err(Error::InvalidDirective {
+                        name: name.into(),
+                        line: line_number,
+                        message: "#version is only allowed in the main shader file".into(),
+                    });
+                },
+                Some(("version", _)) if !self.defines.is_empty() => {
+                    self.output.push_str(line);
+                    self.output.push('\n');
+                    self.output.push_str(&self.defines);
+                    self.output.push_str(&format!("#line {} 0\n", line_number + 1));
+                },
+                _ => {
+                    self.output.push_str(line);
+                    self.output.push('\n');
+                },
+            }
+        }
+        self.stack.pop();
+        Ok(())
+    }
+
+    /// Resolves the argument of an include directive against the directory of
+    /// the including file.
+    fn include_name(&self, including: &str, line: usize, argument: &str) -> Result<String, Error> {
+        let invalid = |message: &str| Error::InvalidDirective {
+            name: including.into(),
+            line,
+            message: message.into(),
+        };
+        if !argument.starts_with('"') {
+            return Err(invalid("expected a quoted file name after #include"));
+        }
+        let end = argument[1..].find('"').ok_or_else(|| invalid("unterminated file name in #include"))? + 1;
+        let trailing = argument[end + 1..].trim();
+        if !(trailing.is_empty() || trailing.starts_with("//")) {
+            return Err(invalid("unexpected characters after #include"));
+        }
+        let file = &argument[1..end];
+        let joined = match including.rfind('/') {
+            Some(slash) => format!("{}/{}", &including[..slash], file),
+            None => file.to_owned(),
+        };
+        resources::normalize_resource_name(&joined).map_err(|inner| Error::ResourceLoad {
+            name: joined.clone(),
+            inner,
+        })
+    }
+}
+
+fn is_directive(line: &str, directive: &str) -> bool {
+    parse_directive(line).map_or(false, |(name, _)| name == directive)
+}
+
+/// Splits a preprocessor directive into its name and the rest of the line.
+fn parse_directive(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    if !line.starts_with('#') {
+        return None;
+    }
+    let line = line[1..].trim_start();
+    let name_len = line.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(line.len());
+    Some((&line[..name_len], line[name_len..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::MemorySource;
+
+    fn resources() -> Resources {
+        with_files(&[("shaders/a.frag", "#version 330\nvoid main() {}\n")])
+    }
+
+    fn with_files(files: &[(&str, &str)]) -> Resources {
+        let mut source = MemorySource::new();
+        for &(name, text) in files {
+            source.insert(name, text);
+        }
+        let mut resources = Resources::new();
+        resources.mount("", source);
+        resources
+    }
+
+    fn expanded(resources: &Resources, name: &str) -> String {
+        preprocess(resources, name, &[]).unwrap().source.into_string().unwrap()
+    }
+
+    fn cycle(resources: &Resources, name: &str) -> String {
+        match preprocess(resources, name, &[]) {
+            Err(Error::IncludeCycle { chain, .. }) => chain,
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn marks_lines_around_an_include() {
+        let resources = with_files(&[
+            ("shaders/main.frag", "#version 330\n#include \"x.glsl\"\nvoid main() {}\n"),
+            ("shaders/x.glsl", "float x;\n"),
+        ]);
+        assert_eq!(
+            expanded(&resources, "shaders/main.frag"),
+            "#version 330\n#line 1 1\nfloat x;\n#line 3 0\nvoid main() {}\n"
+        );
+        let source_map = preprocess(&resources, "shaders/main.frag", &[]).unwrap().source_map;
+        assert_eq!(source_map.file(0), Some("shaders/main.frag"));
+        assert_eq!(source_map.file(1), Some("shaders/x.glsl"));
+        assert_eq!(source_map.file(2), None);
+        assert_eq!(source_map.source("shaders/x.glsl"), Some("float x;\n"));
+    }
+
+    #[test]
+    fn resolves_nested_includes_against_the_including_file() {
+        let resources = with_files(&[
+            ("shaders/main.frag", "#version 330\n#include \"lib/a.glsl\"\nvoid main() {}\n"),
+            ("shaders/lib/a.glsl", "#include \"b.glsl\"\n#include \"../common.glsl\"\nfloat a;\n"),
+            ("shaders/lib/b.glsl", "float b;\n"),
+            ("shaders/common.glsl", "float c;\n"),
+        ]);
+        assert_eq!(expanded(&resources, "shaders/main.frag"), concat!(
+            "#version 330\n",
+            "#line 1 1\n",
+            "#line 1 2\n",
+            "float b;\n",
+            "#line 2 1\n",
+            "#line 1 3\n",
+            "float c;\n",
+            "#line 3 1\n",
+            "float a;\n",
+            "#line 3 0\n",
+            "void main() {}\n",
+        ));
+        let source_map = preprocess(&resources, "shaders/main.frag", &[]).unwrap().source_map;
+        assert_eq!(source_map.files(), &[
+            "shaders/main.frag".to_owned(),
+            "shaders/lib/a.glsl".to_owned(),
+            "shaders/lib/b.glsl".to_owned(),
+            "shaders/common.glsl".to_owned(),
+        ][..]);
+    }
+
+    #[test]
+    fn rejects_a_file_including_itself() {
+        let resources = with_files(&[("shaders/a.glsl", "#include \"a.glsl\"\n")]);
+        assert_eq!(cycle(&resources, "shaders/a.glsl"), "shaders/a.glsl -> shaders/a.glsl");
+    }
+
+    #[test]
+    fn rejects_include_cycles() {
+        let resources = with_files(&[
+            ("shaders/a.frag", "#version 330\n#include \"b.glsl\"\n"),
+            ("shaders/b.glsl", "#include \"a.frag\"\n"),
+        ]);
+        assert_eq!(cycle(&resources, "shaders/a.frag"), "shaders/a.frag -> shaders/b.glsl -> shaders/a.frag");
+    }
+
+    #[test]
+    fn defines_follow_the_version() {
+        let preprocessed = preprocess(&resources(), "shaders/a.frag", &[("A", "1")]).unwrap();
+        let source = preprocessed.source.to_str().unwrap();
+        assert!(source.starts_with("#version 330\n#define A 1\n"), "{}", source);
+    }
+
+    #[test]
+    fn rejects_duplicate_defines() {
+        match preprocess(&resources(), "shaders/a.frag", &[("A", "1"), ("B", "2"), ("A", "1")]) {
+            Err(Error::DuplicateDefine { define, .. }) => assert_eq!(define, "A"),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
+
+/// An active uniform or vertex attribute of a linked program.
+#[derive(Clone, Debug)]
+pub struct ActiveVariable {
+    pub name: String,
+    /// The GLSL type, e.g. `gl::FLOAT_MAT4`.
+    pub kind: GLenum,
+    /// Number of array elements, 1 for non-arrays.
+    pub size: GLint,
+    /// -1 for built-in attributes.
+    pub location: GLint,
+}
+
+impl ActiveVariable {
+    pub fn type_name(&self) -> &'static str {
+        type_name(self.kind)
+    }
+}
+
+/// The active uniforms and attributes of a program, as reported by the
+/// driver after linking.
+///
+/// Array uniforms can be looked up both as `lights` and `lights[0]`. Members
+/// of uniform blocks have no location and are left out.
+#[derive(Clone, Debug, Default)]
+pub struct Reflection {
+    uniforms: HashMap<String, ActiveVariable>,
+    attributes: HashMap<String, ActiveVariable>,
+}
+
+impl Reflection {
+    pub fn query(gl: &gl::Gl, program: GLuint) -> Reflection {
+        let mut uniforms = HashMap::new();
+        for mut uniform in active_variables(gl, program, gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH, |gl, index, buffer_len, length, size, kind, name| unsafe {
+            gl.GetActiveUniform(program, index, buffer_len, length, size, kind, name)
+        }) {
+            let name = std::ffi::CString::new(uniform.name.clone()).unwrap();
+            uniform.location = unsafe { gl.GetUniformLocation(program, name.as_ptr()) };
+            if uniform.location < 0 {
+                continue;
+            }
+            if uniform.name.ends_with("[0]") {
+                let base_name = uniform.name[..uniform.name.len() - 3].to_owned();
+                uniforms.insert(base_name, uniform.clone());
+            }
+            uniforms.insert(uniform.name.clone(), uniform);
+        }
+
+        let mut attributes = HashMap::new();
+        for mut attribute in active_variables(gl, program, gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, |gl, index, buffer_len, length, size, kind, name| unsafe {
+            gl.GetActiveAttrib(program, index, buffer_len, length, size, kind, name)
+        }) {
+            let name = std::ffi::CString::new(attribute.name.clone()).unwrap();
+            attribute.location = unsafe { gl.GetAttribLocation(program, name.as_ptr()) };
+            attributes.insert(attribute.name.clone(), attribute);
+        }
+
+        Reflection {
+            uniforms,
+            attributes,
+        }
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable> {
+        self.uniforms.get(name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&ActiveVariable> {
+        self.attributes.get(name)
+    }
+
+    /// Every active uniform, ordered by location.
+    pub fn uniforms(&self) -> Vec<&ActiveVariable> {
+        let mut uniforms = self.uniforms.iter()
+            .filter(|&(name, uniform)| name == &uniform.name)
+            .map(|(_, uniform)| uniform)
+            .collect::<Vec<_>>();
+        uniforms.sort_by_key(|uniform| uniform.location);
+        uniforms
+    }
+
+    /// Every active attribute, ordered by location.
+    pub fn attributes(&self) -> Vec<&ActiveVariable> {
+        let mut attributes = self.attributes.values().collect::<Vec<_>>();
+        attributes.sort_by_key(|attribute| attribute.location);
+        attributes
+    }
+}
+
+fn active_variables<F>(gl: &gl::Gl, program: GLuint, count: GLenum, max_length: GLenum, get_active: F) -> Vec<ActiveVariable>
+where F: Fn(&gl::Gl, GLuint, GLsizei, *mut GLsizei, *mut GLint, *mut GLenum, *mut GLchar) {
+    let mut variable_count: GLint = 0;
+    let mut name_capacity: GLint = 0;
+    unsafe {
+        gl.GetProgramiv(program, count, &mut variable_count);
+        gl.GetProgramiv(program, max_length, &mut name_capacity);
+    }
+    let mut variables = vec!();
+    for index in 0..variable_count.max(0) as GLuint {
+        let mut name = vec![0u8; name_capacity.max(1) as usize];
+        let mut length: GLsizei = 0;
+        let mut size: GLint = 0;
+        let mut kind: GLenum = 0;
+        get_active(gl, index, name.len() as GLsizei, &mut length, &mut size, &mut kind, name.as_mut_ptr() as *mut GLchar);
+        name.truncate(length.max(0) as usize);
+        variables.push(ActiveVariable {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            kind,
+            size,
+            location: -1,
+        });
+    }
+    variables
+}
+
+/// The GLSL name of a type reported by `glGetActiveUniform`.
+pub fn type_name(kind: GLenum) -> &'static str {
+    match kind {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::DOUBLE => "double",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::FLOAT_MAT2x3 => "mat2x3",
+        gl::FLOAT_MAT2x4 => "mat2x4",
+        gl::FLOAT_MAT3x2 => "mat3x2",
+        gl::FLOAT_MAT3x4 => "mat3x4",
+        gl::FLOAT_MAT4x2 => "mat4x2",
+        gl::FLOAT_MAT4x3 => "mat4x3",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+        gl::SAMPLER_BUFFER => "samplerBuffer",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        _ if is_sampler(kind) => "sampler",
+        _ => "unknown",
+    }
+}
+
+/// The number of components of `float` and the float vectors, `None` for
+/// every other type.
+pub fn float_components(kind: GLenum) -> Option<usize> {
+    match kind {
+        gl::FLOAT => Some(1),
+        gl::FLOAT_VEC2 => Some(2),
+        gl::FLOAT_VEC3 => Some(3),
+        gl::FLOAT_VEC4 => Some(4),
+        _ => None,
+    }
+}
+
+/// Whether `kind` is any of the sampler types, which are set through a
+/// texture unit.
+pub fn is_sampler(kind: GLenum) -> bool {
+    matches!(kind,
+        gl::SAMPLER_1D | gl::SAMPLER_2D | gl::SAMPLER_3D | gl::SAMPLER_CUBE |
+        gl::SAMPLER_1D_SHADOW | gl::SAMPLER_2D_SHADOW | gl::SAMPLER_CUBE_SHADOW |
+        gl::SAMPLER_1D_ARRAY | gl::SAMPLER_2D_ARRAY | gl::SAMPLER_1D_ARRAY_SHADOW |
+        gl::SAMPLER_2D_ARRAY_SHADOW | gl::SAMPLER_2D_MULTISAMPLE |
+        gl::SAMPLER_2D_MULTISAMPLE_ARRAY | gl::SAMPLER_BUFFER | gl::SAMPLER_2D_RECT |
+        gl::SAMPLER_2D_RECT_SHADOW | gl::SAMPLER_CUBE_MAP_ARRAY | gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW |
+        gl::INT_SAMPLER_1D | gl::INT_SAMPLER_2D | gl::INT_SAMPLER_3D | gl::INT_SAMPLER_CUBE |
+        gl::INT_SAMPLER_1D_ARRAY | gl::INT_SAMPLER_2D_ARRAY | gl::INT_SAMPLER_2D_MULTISAMPLE |
+        gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY | gl::INT_SAMPLER_BUFFER | gl::INT_SAMPLER_2D_RECT |
+        gl::INT_SAMPLER_CUBE_MAP_ARRAY |
+        gl::UNSIGNED_INT_SAMPLER_1D | gl::UNSIGNED_INT_SAMPLER_2D | gl::UNSIGNED_INT_SAMPLER_3D |
+        gl::UNSIGNED_INT_SAMPLER_CUBE | gl::UNSIGNED_INT_SAMPLER_1D_ARRAY |
+        gl::UNSIGNED_INT_SAMPLER_2D_ARRAY | gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE |
+        gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY | gl::UNSIGNED_INT_SAMPLER_BUFFER |
+        gl::UNSIGNED_INT_SAMPLER_2D_RECT | gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_glsl_types() {
+        for &(kind, name) in &[
+            (gl::FLOAT, "float"),
+            (gl::FLOAT_VEC3, "vec3"),
+            (gl::INT_VEC2, "ivec2"),
+            (gl::UNSIGNED_INT_VEC4, "uvec4"),
+            (gl::BOOL, "bool"),
+            (gl::FLOAT_MAT4, "mat4"),
+            (gl::FLOAT_MAT2x3, "mat2x3"),
+            (gl::SAMPLER_2D, "sampler2D"),
+            (gl::SAMPLER_2D_MULTISAMPLE, "sampler2DMS"),
+            (gl::UNSIGNED_INT_SAMPLER_2D, "usampler2D"),
+            (gl::SAMPLER_CUBE_SHADOW, "sampler"),
+            (gl::IMAGE_2D, "unknown"),
+        ] {
+            assert_eq!(type_name(kind), name, "{:#x}", kind);
+        }
+    }
+
+    #[test]
+    fn tells_samplers_apart() {
+        for &(kind, sampler) in &[
+            (gl::SAMPLER_2D, true),
+            (gl::SAMPLER_2D_ARRAY_SHADOW, true),
+            (gl::INT_SAMPLER_BUFFER, true),
+            (gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY, true),
+            (gl::INT, false),
+            (gl::FLOAT_VEC4, false),
+            (gl::IMAGE_2D, false),
+        ] {
+            assert_eq!(is_sampler(kind), sampler, "{}", type_name(kind));
+        }
+    }
+
+    #[test]
+    fn counts_float_components() {
+        assert_eq!(float_components(gl::FLOAT), Some(1));
+        assert_eq!(float_components(gl::FLOAT_VEC4), Some(4));
+        assert_eq!(float_components(gl::INT_VEC2), None);
+        assert_eq!(float_components(gl::FLOAT_MAT2), None);
+    }
+}
@@ -0,0 +1,133 @@
+use gl::types::{GLenum, GLint, GLuint};
+use nalgebra::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+
+use super::reflection::is_sampler;
+
+/// A value that can be assigned to a uniform with `Program::set_uniform`.
+pub trait UniformValue {
+    /// The GLSL type of the value, for error messages.
+    fn type_name(&self) -> &'static str;
+
+    /// Whether the value can be assigned to a uniform of the given type.
+    fn accepts(&self, kind: GLenum) -> bool;
+
+    /// Uploads the value. The location belongs to `program` and the type has
+    /// been checked with `accepts`.
+    unsafe fn set(&self, gl: &gl::Gl, program: GLuint, location: GLint);
+}
+
+/// The texture unit a sampler uniform reads from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sampler(pub u32);
+
+impl UniformValue for Sampler {
+    fn type_name(&self) -> &'static str {
+        "sampler"
+    }
+
+    fn accepts(&self, kind: GLenum) -> bool {
+        is_sampler(kind)
+    }
+
+    unsafe fn set(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+        gl.ProgramUniform1i(program, location, self.0 as GLint);
+    }
+}
+
+impl UniformValue for bool {
+    fn type_name(&self) -> &'static str {
+        "bool"
+    }
+
+    fn accepts(&self, kind: GLenum) -> bool {
+        kind == gl::BOOL
+    }
+
+    unsafe fn set(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+        gl.ProgramUniform1i(program, location, *self as GLint);
+    }
+}
+
+macro_rules! uniform_value {
+    ($type:ty, $glsl:expr, $kind:expr, |$value:ident, $gl:ident, $program:ident, $location:ident| $set:expr) => {
+        impl UniformValue for $type {
+            fn type_name(&self) -> &'static str {
+                $glsl
+            }
+
+            fn accepts(&self, kind: GLenum) -> bool {
+                kind == $kind
+            }
+
+            unsafe fn set(&self, $gl: &gl::Gl, $program: GLuint, $location: GLint) {
+                let $value = self;
+                $set
+            }
+        }
+    };
+}
+
+uniform_value!(f32, "float", gl::FLOAT, |v, gl, p, l| gl.ProgramUniform1f(p, l, *v));
+uniform_value!(Vector2<f32>, "vec2", gl::FLOAT_VEC2, |v, gl, p, l| gl.ProgramUniform2fv(p, l, 1, v.as_ptr()));
+uniform_value!(Vector3<f32>, "vec3", gl::FLOAT_VEC3, |v, gl, p, l| gl.ProgramUniform3fv(p, l, 1, v.as_ptr()));
+uniform_value!(Vector4<f32>, "vec4", gl::FLOAT_VEC4, |v, gl, p, l| gl.ProgramUniform4fv(p, l, 1, v.as_ptr()));
+uniform_value!(i32, "int", gl::INT, |v, gl, p, l| gl.ProgramUniform1i(p, l, *v));
+uniform_value!(Vector2<i32>, "ivec2", gl::INT_VEC2, |v, gl, p, l| gl.ProgramUniform2iv(p, l, 1, v.as_ptr()));
+uniform_value!(Vector3<i32>, "ivec3", gl::INT_VEC3, |v, gl, p, l| gl.ProgramUniform3iv(p, l, 1, v.as_ptr()));
+uniform_value!(Vector4<i32>, "ivec4", gl::INT_VEC4, |v, gl, p, l| gl.ProgramUniform4iv(p, l, 1, v.as_ptr()));
+uniform_value!(u32, "uint", gl::UNSIGNED_INT, |v, gl, p, l| gl.ProgramUniform1ui(p, l, *v));
+uniform_value!(Vector2<u32>, "uvec2", gl::UNSIGNED_INT_VEC2, |v, gl, p, l| gl.ProgramUniform2uiv(p, l, 1, v.as_ptr()));
+uniform_value!(Vector3<u32>, "uvec3", gl::UNSIGNED_INT_VEC3, |v, gl, p, l| gl.ProgramUniform3uiv(p, l, 1, v.as_ptr()));
+uniform_value!(Vector4<u32>, "uvec4", gl::UNSIGNED_INT_VEC4, |v, gl, p, l| gl.ProgramUniform4uiv(p, l, 1, v.as_ptr()));
+// nalgebra stores matrices column-major, like GL expects them
+uniform_value!(Matrix2<f32>, "mat2", gl::FLOAT_MAT2, |v, gl, p, l| gl.ProgramUniformMatrix2fv(p, l, 1, gl::FALSE, v.as_ptr()));
+uniform_value!(Matrix3<f32>, "mat3", gl::FLOAT_MAT3, |v, gl, p, l| gl.ProgramUniformMatrix3fv(p, l, 1, gl::FALSE, v.as_ptr()));
+uniform_value!(Matrix4<f32>, "mat4", gl::FLOAT_MAT4, |v, gl, p, l| gl.ProgramUniformMatrix4fv(p, l, 1, gl::FALSE, v.as_ptr()));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::reflection::type_name;
+
+    const KINDS: &[GLenum] = &[
+        gl::FLOAT, gl::FLOAT_VEC2, gl::FLOAT_VEC3, gl::FLOAT_VEC4,
+        gl::INT, gl::INT_VEC2, gl::INT_VEC3, gl::INT_VEC4,
+        gl::UNSIGNED_INT, gl::UNSIGNED_INT_VEC2, gl::UNSIGNED_INT_VEC3, gl::UNSIGNED_INT_VEC4,
+        gl::BOOL, gl::FLOAT_MAT2, gl::FLOAT_MAT3, gl::FLOAT_MAT4, gl::SAMPLER_2D,
+    ];
+
+    #[test]
+    fn values_accept_exactly_their_glsl_type() {
+        let values: &[(&dyn UniformValue, GLenum)] = &[
+            (&1.0f32, gl::FLOAT),
+            (&Vector2::new(1.0f32, 2.0), gl::FLOAT_VEC2),
+            (&Vector3::new(1.0f32, 2.0, 3.0), gl::FLOAT_VEC3),
+            (&Vector4::new(1.0f32, 2.0, 3.0, 4.0), gl::FLOAT_VEC4),
+            (&1i32, gl::INT),
+            (&Vector2::new(1i32, 2), gl::INT_VEC2),
+            (&Vector3::new(1i32, 2, 3), gl::INT_VEC3),
+            (&Vector4::new(1i32, 2, 3, 4), gl::INT_VEC4),
+            (&1u32, gl::UNSIGNED_INT),
+            (&Vector2::new(1u32, 2), gl::UNSIGNED_INT_VEC2),
+            (&Vector3::new(1u32, 2, 3), gl::UNSIGNED_INT_VEC3),
+            (&Vector4::new(1u32, 2, 3, 4), gl::UNSIGNED_INT_VEC4),
+            (&true, gl::BOOL),
+            (&Matrix2::<f32>::identity(), gl::FLOAT_MAT2),
+            (&Matrix3::<f32>::identity(), gl::FLOAT_MAT3),
+            (&Matrix4::<f32>::identity(), gl::FLOAT_MAT4),
+        ];
+        for &(value, kind) in values {
+            assert_eq!(value.type_name(), type_name(kind));
+            for &other in KINDS {
+                assert_eq!(value.accepts(other), other == kind, "{} given a {}", value.type_name(), type_name(other));
+            }
+        }
+    }
+
+    #[test]
+    fn samplers_accept_every_sampler_type() {
+        assert!(Sampler(0).accepts(gl::SAMPLER_2D));
+        assert!(Sampler(0).accepts(gl::UNSIGNED_INT_SAMPLER_3D));
+        assert!(!Sampler(0).accepts(gl::INT));
+    }
+}
@@ -0,0 +1,106 @@
+use gl;
+use nalgebra::{Vector2, Vector4};
+use rustgl_render_derive::VertexAttribPointers;
+
+use super::array_buffer::{BufferTypeArray, Topology, VertexArray};
+use super::ring_buffer::{self, RingBuffer, DEFAULT_SECTIONS};
+use super::shaders::Program;
+use super::types::f32_f32;
+
+/// A filled rectangle in pixels from the top left corner of the window.
+#[derive(Copy, Clone, Debug)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub color: Vector4<f32>,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32, color: Vector4<f32>) -> Rect {
+        Rect { x, y, width, height, color }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+#[derive(VertexAttribPointers)]
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct RectVertex {
+    #[location = 0]
+    position: f32_f32,
+}
+
+/// Every rectangle is two triangles.
+const VERTICES_PER_RECT: usize = 6;
+
+/// How many rectangles a frame can draw before waiting on the GPU.
+const RECTS_PER_FRAME: usize = 512;
+
+/// Draws solid rectangles for debug overlays, with the `rect` shader from
+/// the assets. The vertices go to a ring buffer, so `end_frame` has to be
+/// called once a frame.
+pub struct RectRenderer {
+    gl: gl::Gl,
+    vertices: RingBuffer<BufferTypeArray, RectVertex>,
+    vao: VertexArray,
+}
+
+impl RectRenderer {
+    pub fn new(gl: &gl::Gl) -> Result<RectRenderer, ring_buffer::Error> {
+        let vertices = RingBuffer::new(gl, RECTS_PER_FRAME * VERTICES_PER_RECT, DEFAULT_SECTIONS)?;
+        let mut vao = VertexArray::new(gl);
+        vao.set_ring_buffer(&vertices);
+        vao.set_topology(Topology::Triangles);
+        Ok(RectRenderer {
+            gl: gl.clone(),
+            vertices,
+            vao,
+        })
+    }
+
+    /// Draws `rects` in order, later ones on top, in a window that is
+    /// `screen_size` pixels large.
+    pub fn draw(&mut self, program: &Program, rects: &[Rect], screen_size: (i32, i32)) -> Result<(), failure::Error> {
+        if rects.is_empty() {
+            return Ok(());
+        }
+        let vertices = rects.iter()
+            .flat_map(|rect| {
+                let (x0, y0) = (rect.x, rect.y);
+                let (x1, y1) = (rect.x + rect.width, rect.y + rect.height);
+                vec![(x0, y0), (x1, y0), (x0, y1), (x0, y1), (x1, y0), (x1, y1)]
+            })
+            .map(|corner| RectVertex { position: corner.into() })
+            .collect::<Vec<_>>();
+        program.activate();
+        program.set_uniform("screenSize", &Vector2::new(screen_size.0 as f32, screen_size.1 as f32))?;
+        unsafe {
+            self.gl.Enable(gl::BLEND);
+            self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        let (ring, vao) = (&mut self.vertices, &self.vao);
+        let chunk_len = ring.capacity() / VERTICES_PER_RECT;
+        let drawn = rects.chunks(chunk_len).zip(vertices.chunks(chunk_len * VERTICES_PER_RECT)).try_for_each(|(rects, vertices)| {
+            let first = ring.write_or_advance(vertices).expect("chunks fit a section");
+            rects.iter().enumerate().try_for_each(|(index, rect)| -> Result<(), failure::Error> {
+                program.set_uniform("color", &rect.color)?;
+                vao.draw_range(first + index * VERTICES_PER_RECT, VERTICES_PER_RECT)?;
+                Ok(())
+            })
+        });
+        unsafe {
+            self.gl.Disable(gl::BLEND);
+        }
+        drawn
+    }
+
+    /// Call once a frame after the last `draw`, see `RingBuffer::end_frame`.
+    pub fn end_frame(&mut self) {
+        self.vertices.end_frame();
+    }
+}
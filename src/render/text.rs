@@ -0,0 +1,135 @@
+use gl;
+use nalgebra::{Vector2, Vector4};
+use rustgl_render_derive::VertexAttribPointers;
+
+use super::array_buffer::{BufferTypeArray, Topology, VertexArray};
+use super::font::Font;
+use super::ring_buffer::{self, RingBuffer, DEFAULT_SECTIONS};
+use super::shaders::{Program, Sampler};
+use super::types::f32_f32;
+
+/// A corner of a glyph quad, positioned in pixels from the top left corner of
+/// the window.
+#[derive(VertexAttribPointers)]
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct TextVertex {
+    #[location = 0]
+    pub position: f32_f32,
+    #[location = 1]
+    pub tex_coord: f32_f32,
+}
+
+/// Tabs are expanded to this many spaces.
+const TAB_WIDTH: usize = 4;
+
+/// Every glyph is a quad of two triangles.
+const VERTICES_PER_GLYPH: usize = 6;
+
+/// How many glyphs a frame can draw before waiting on the GPU.
+const GLYPHS_PER_FRAME: usize = 2048;
+
+/// Draws strings with a font atlas, one quad per glyph. Meant for debug
+/// output such as shader errors, the quads are rebuilt on every call and
+/// written to a ring buffer, so `end_frame` has to be called once a frame.
+pub struct TextRenderer {
+    gl: gl::Gl,
+    vertices: RingBuffer<BufferTypeArray, TextVertex>,
+    vao: VertexArray,
+}
+
+impl TextRenderer {
+    pub fn new(gl: &gl::Gl) -> Result<TextRenderer, ring_buffer::Error> {
+        let vertices = RingBuffer::new(gl, GLYPHS_PER_FRAME * VERTICES_PER_GLYPH, DEFAULT_SECTIONS)?;
+        let mut vao = VertexArray::new(gl);
+        vao.set_ring_buffer(&vertices);
+        vao.set_topology(Topology::Triangles);
+        Ok(TextRenderer {
+            gl: gl.clone(),
+            vertices,
+            vao,
+        })
+    }
+
+    /// Draws `text` with its top left corner at `position`, in pixels of a
+    /// window that is `screen_size` large. Lines that don't fit the width of
+    /// the window are wrapped. `program` is expected to be the `font` shader
+    /// from the assets.
+    pub fn draw(&mut self, program: &Program, font: &Font, text: &str, position: (f32, f32), color: Vector4<f32>, screen_size: (i32, i32)) -> Result<(), failure::Error> {
+        let vertices = layout(font, text, position, screen_size.0 as f32);
+        if vertices.is_empty() {
+            return Ok(());
+        }
+        program.activate();
+        program.set_uniform_if_active("screenSize", &Vector2::new(screen_size.0 as f32, screen_size.1 as f32))?;
+        program.set_uniform_if_active("textColor", &color)?;
+        program.set_uniform_if_active("fontTexture", &Sampler(0))?;
+        font.texture().activate_texture_unit(0);
+        font.texture().bind();
+        unsafe {
+            self.gl.Enable(gl::BLEND);
+            self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        let (ring, vao) = (&mut self.vertices, &self.vao);
+        let drawn = vertices.chunks(ring.capacity()).try_for_each(|chunk| {
+            let first = ring.write_or_advance(chunk).expect("chunks fit a section");
+            vao.draw_range(first, chunk.len())
+        });
+        unsafe {
+            self.gl.Disable(gl::BLEND);
+        }
+        drawn?;
+        Ok(())
+    }
+
+    /// Call once a frame after the last `draw`, see `RingBuffer::end_frame`.
+    pub fn end_frame(&mut self) {
+        self.vertices.end_frame();
+    }
+}
+
+/// Two triangles for every visible glyph of `text`.
+fn layout(font: &Font, text: &str, (left, top): (f32, f32), max_width: f32) -> Vec<TextVertex> {
+    let (atlas_width, atlas_height) = font.atlas_size();
+    let line_height = font.line_height();
+    let mut vertices = vec!();
+    let mut pen = Vector2::new(left, top + line_height);
+    let chars = text.chars().flat_map(|character| {
+        let count = if character == '\t' { TAB_WIDTH } else { 1 };
+        std::iter::repeat(if character == '\t' { ' ' } else { character }).take(count)
+    });
+    for character in chars {
+        if character == '\n' {
+            pen = Vector2::new(left, pen.y + line_height);
+            continue;
+        }
+        let glyph = match font.glyph(character).or_else(|| font.glyph('?')) {
+            Some(glyph) => glyph,
+            None => continue,
+        };
+        if pen.x + glyph.advance > max_width && pen.x > left {
+            pen = Vector2::new(left, pen.y + line_height);
+        }
+        if let Some(region) = glyph.region {
+            let x0 = (pen.x + glyph.offset.x as f32).round();
+            let y0 = (pen.y + glyph.offset.y as f32).round();
+            let (x1, y1) = (x0 + region.z as f32, y0 + region.w as f32);
+            let u0 = region.x as f32 / atlas_width as f32;
+            let v0 = region.y as f32 / atlas_height as f32;
+            let u1 = (region.x + region.z) as f32 / atlas_width as f32;
+            let v1 = (region.y + region.w) as f32 / atlas_height as f32;
+            let corner = |x: f32, y: f32, u: f32, v: f32| TextVertex { position: (x, y).into(), tex_coord: (u, v).into() };
+            let (top_right, bottom_left) = (corner(x1, y0, u1, v0), corner(x0, y1, u0, v1));
+            vertices.extend_from_slice(&[
+                corner(x0, y0, u0, v0),
+                top_right,
+                bottom_left,
+                bottom_left,
+                top_right,
+                corner(x1, y1, u1, v1),
+            ]);
+        }
+        pen.x += glyph.advance;
+    }
+    vertices
+}
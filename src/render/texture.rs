@@ -1,5 +1,7 @@
 use gl;
 
+use crate::resources::{Image, PixelFormat};
+
 pub struct Texture {
     gl: gl::Gl,
     texture_id: gl::types::GLuint,
@@ -10,7 +12,33 @@ impl Texture {
         gl: &gl::Gl,
         width: u32, 
         height: u32,
-        data: *const std::os::raw::c_void,
+        data: &[u8],
+    ) -> Texture {
+        Texture::from_pixels(gl, width, height, gl::RED, data)
+    }
+
+    pub fn from_image(gl: &gl::Gl, image: &Image) -> Texture {
+        let format = match image.format {
+            PixelFormat::Gray => gl::RED,
+            PixelFormat::GrayAlpha => gl::RG,
+            PixelFormat::Rgb => gl::RGB,
+            PixelFormat::Rgba => gl::RGBA,
+        };
+        Texture::from_pixels(
+            gl,
+            image.width,
+            image.height,
+            format,
+            &image.pixels,
+        )
+    }
+
+    fn from_pixels(
+        gl: &gl::Gl,
+        width: u32,
+        height: u32,
+        format: gl::types::GLenum,
+        data: &[u8],
     ) -> Texture {
         let mut texture_id: gl::types::GLuint = 0;
         unsafe {
@@ -27,13 +55,13 @@ impl Texture {
             gl.TexImage2D(
                 gl::TEXTURE_2D,
                 0,
-                gl::RED as gl::types::GLint,
+                format as gl::types::GLint,
                 width as i32,
                 height as i32,
                 0,
-                gl::RED,
+                format,
                 gl::UNSIGNED_BYTE,
-                data,
+                data.as_ptr() as *const std::os::raw::c_void,
             );
             gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
             gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
@@ -42,6 +70,9 @@ impl Texture {
         }
         texture
     }
+    pub fn id(&self) -> gl::types::GLuint {
+        self.texture_id
+    }
     pub fn bind(&self) {
         unsafe {
             self.gl.BindTexture(gl::TEXTURE_2D, self.texture_id);
@@ -57,4 +88,12 @@ impl Texture {
             self.gl.ActiveTexture(gl::TEXTURE0 + index);
         }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteTextures(1, &mut self.texture_id);
+        }
+    }
+}
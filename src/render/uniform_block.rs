@@ -0,0 +1,290 @@
+//! Rust structs laid out as GLSL uniform blocks.
+//!
+//! `#[derive(UniformBlock)]` on a struct of `f32`, `i32`, `u32`, vector and
+//! matrix fields generates a `#[repr(C)]` companion struct named after it
+//! with a `Std140` suffix. The companion holds the same fields converted to
+//! their std140 representation with explicit padding in between, so its bytes
+//! can be uploaded as they are. The derive checks at compile time that the
+//! companion's Rust layout puts every field at its std140 offset.
+//!
+//! ```ignore
+//! #[derive(UniformBlock)]
+//! pub struct Camera {
+//!     pub view_projection: Matrix4<f32>,
+//!     pub position: Vector3<f32>,
+//!     pub exposure: f32,
+//! }
+//! ```
+//!
+//! matches
+//!
+//! ```glsl
+//! layout (std140) uniform Camera {
+//!     mat4 view_projection;
+//!     vec3 position;
+//!     float exposure;
+//! };
+//! ```
+//!
+//! Field order matters, the GLSL block has to list its members in the same
+//! order. Arrays of any of these types and other derived blocks can be used as
+//! fields too.
+
+use nalgebra::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+
+/// A type that can be a member of a std140 uniform block.
+pub trait Std140 {
+    /// The base alignment from the std140 rules.
+    const ALIGN: usize;
+    /// The std140 representation, without padding after it.
+    type Padded: Copy;
+    /// The representation as an array element, whose size is rounded up to a
+    /// multiple of 16.
+    type ArrayElement: Copy;
+
+    fn to_std140(&self) -> Self::Padded;
+    fn to_std140_array_element(&self) -> Self::ArrayElement;
+}
+
+/// A struct that can be uploaded as a std140 uniform block, see the module
+/// documentation.
+pub trait UniformBlock {
+    /// The generated `#[repr(C)]` struct with the std140 layout.
+    type Std140: Copy;
+    /// The size of the block in bytes, which is what the driver reports as
+    /// `GL_UNIFORM_BLOCK_DATA_SIZE`.
+    const SIZE: usize;
+
+    fn to_std140(&self) -> Self::Std140;
+
+    /// The block as bytes, ready for `glBufferSubData`.
+    fn std140_bytes(&self) -> Vec<u8> {
+        let block = self.to_std140();
+        let bytes = unsafe {
+            std::slice::from_raw_parts(&block as *const Self::Std140 as *const u8, std::mem::size_of::<Self::Std140>())
+        };
+        bytes.to_vec()
+    }
+}
+
+/// Rounds `offset` up to the next multiple of `align`.
+pub const fn align_to(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+macro_rules! std140_scalar {
+    ($type:ty) => {
+        impl Std140 for $type {
+            const ALIGN: usize = 4;
+            type Padded = $type;
+            type ArrayElement = [$type; 4];
+
+            fn to_std140(&self) -> $type {
+                *self
+            }
+
+            fn to_std140_array_element(&self) -> [$type; 4] {
+                [*self, 0 as $type, 0 as $type, 0 as $type]
+            }
+        }
+    };
+}
+
+std140_scalar!(f32);
+std140_scalar!(i32);
+std140_scalar!(u32);
+
+macro_rules! std140_vector {
+    ($scalar:ty) => {
+        impl Std140 for Vector2<$scalar> {
+            const ALIGN: usize = 8;
+            type Padded = [$scalar; 2];
+            type ArrayElement = [$scalar; 4];
+
+            fn to_std140(&self) -> [$scalar; 2] {
+                [self.x, self.y]
+            }
+
+            fn to_std140_array_element(&self) -> [$scalar; 4] {
+                [self.x, self.y, 0 as $scalar, 0 as $scalar]
+            }
+        }
+
+        impl Std140 for Vector3<$scalar> {
+            const ALIGN: usize = 16;
+            type Padded = [$scalar; 3];
+            type ArrayElement = [$scalar; 4];
+
+            fn to_std140(&self) -> [$scalar; 3] {
+                [self.x, self.y, self.z]
+            }
+
+            fn to_std140_array_element(&self) -> [$scalar; 4] {
+                [self.x, self.y, self.z, 0 as $scalar]
+            }
+        }
+
+        impl Std140 for Vector4<$scalar> {
+            const ALIGN: usize = 16;
+            type Padded = [$scalar; 4];
+            type ArrayElement = [$scalar; 4];
+
+            fn to_std140(&self) -> [$scalar; 4] {
+                [self.x, self.y, self.z, self.w]
+            }
+
+            fn to_std140_array_element(&self) -> [$scalar; 4] {
+                self.to_std140()
+            }
+        }
+    };
+}
+
+std140_vector!(f32);
+std140_vector!(i32);
+std140_vector!(u32);
+
+// Matrices are stored as arrays of column vectors, so every column takes up a
+// whole vec4.
+macro_rules! std140_matrix {
+    ($type:ty, $columns:expr) => {
+        impl Std140 for $type {
+            const ALIGN: usize = 16;
+            type Padded = [[f32; 4]; $columns];
+            type ArrayElement = [[f32; 4]; $columns];
+
+            fn to_std140(&self) -> [[f32; 4]; $columns] {
+                let mut columns = [[0.0; 4]; $columns];
+                for (column, padded) in columns.iter_mut().enumerate() {
+                    for row in 0..$columns {
+                        padded[row] = self[(row, column)];
+                    }
+                }
+                columns
+            }
+
+            fn to_std140_array_element(&self) -> [[f32; 4]; $columns] {
+                self.to_std140()
+            }
+        }
+    };
+}
+
+std140_matrix!(Matrix2<f32>, 2);
+std140_matrix!(Matrix3<f32>, 3);
+std140_matrix!(Matrix4<f32>, 4);
+
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGN: usize = align_to(T::ALIGN, 16);
+    type Padded = [T::ArrayElement; N];
+    type ArrayElement = [T::ArrayElement; N];
+
+    fn to_std140(&self) -> Self::Padded {
+        std::array::from_fn(|index| self[index].to_std140_array_element())
+    }
+
+    fn to_std140_array_element(&self) -> Self::ArrayElement {
+        self.to_std140()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustgl_render_derive::UniformBlock;
+
+    #[derive(UniformBlock)]
+    struct Camera {
+        view_projection: Matrix4<f32>,
+        position: Vector3<f32>,
+        exposure: f32,
+    }
+
+    #[derive(UniformBlock)]
+    struct Vectors {
+        a: Vector3<f32>,
+        b: Vector3<f32>,
+        c: Vector2<f32>,
+    }
+
+    #[derive(UniformBlock)]
+    struct Arrays {
+        weights: [f32; 3],
+        offset: Vector2<f32>,
+        colors: [Vector3<f32>; 2],
+        count: i32,
+    }
+
+    #[derive(UniformBlock)]
+    struct Nested {
+        time: f32,
+        camera: Camera,
+        cameras: [Camera; 2],
+    }
+
+    #[derive(UniformBlock)]
+    struct Empty {
+        nothing: [f32; 0],
+        value: f32,
+    }
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        f32::from_ne_bytes(word)
+    }
+
+    #[test]
+    fn lays_out_scalars_after_vec3() {
+        assert_eq!(Camera::STD140_OFFSETS, [0, 64, 76]);
+        assert_eq!(Camera::SIZE, 80);
+        let camera = Camera {
+            view_projection: Matrix4::new_scaling(2.0),
+            position: Vector3::new(1.0, 2.0, 3.0),
+            exposure: 0.5,
+        };
+        let bytes = camera.std140_bytes();
+        assert_eq!(bytes.len(), 80);
+        assert_eq!(f32_at(&bytes, 0), 2.0);
+        assert_eq!(f32_at(&bytes, 60), 1.0);
+        assert_eq!(f32_at(&bytes, 72), 3.0);
+        assert_eq!(f32_at(&bytes, 76), 0.5);
+    }
+
+    #[test]
+    fn aligns_vec3_to_16_bytes() {
+        assert_eq!(Vectors::STD140_OFFSETS, [0, 16, 32]);
+        assert_eq!(Vectors::SIZE, 48);
+    }
+
+    #[test]
+    fn strides_arrays_by_16_bytes() {
+        assert_eq!(Arrays::STD140_OFFSETS, [0, 48, 64, 96]);
+        assert_eq!(Arrays::SIZE, 112);
+        let arrays = Arrays {
+            weights: [1.0, 2.0, 3.0],
+            offset: Vector2::new(4.0, 5.0),
+            colors: [Vector3::new(6.0, 7.0, 8.0), Vector3::new(9.0, 10.0, 11.0)],
+            count: 0,
+        };
+        let bytes = arrays.std140_bytes();
+        assert_eq!(bytes.len(), 112);
+        assert_eq!([f32_at(&bytes, 0), f32_at(&bytes, 16), f32_at(&bytes, 32)], [1.0, 2.0, 3.0]);
+        assert_eq!([f32_at(&bytes, 48), f32_at(&bytes, 52)], [4.0, 5.0]);
+        assert_eq!([f32_at(&bytes, 64), f32_at(&bytes, 80), f32_at(&bytes, 88)], [6.0, 9.0, 11.0]);
+    }
+
+    #[test]
+    fn nests_blocks() {
+        assert_eq!(Nested::STD140_OFFSETS, [0, 16, 96]);
+        assert_eq!(Nested::SIZE, 256);
+    }
+
+    #[test]
+    fn allows_empty_arrays() {
+        assert_eq!(Empty::STD140_OFFSETS, [0, 0]);
+        assert_eq!(Empty::SIZE, 16);
+        let bytes = Empty { nothing: [], value: 1.5 }.std140_bytes();
+        assert_eq!(f32_at(&bytes, 0), 1.5);
+    }
+}
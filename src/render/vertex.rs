@@ -1,6 +1,18 @@
 use super::types::f32_f32_f32;
 use rustgl_render_derive::VertexAttribPointers;
 
+/// A vertex type whose attribute layout can be set up on a vertex array.
+/// Implemented by `#[derive(VertexAttribPointers)]`.
+pub trait VertexLayout: Copy {
+    /// How many instances share one element when the type is used as an
+    /// instance buffer: the smallest `#[divisor = ?]` of its fields, 0 if any
+    /// field has none and so advances per vertex.
+    const DIVISOR: usize;
+
+    /// Enables and points every attribute at the bound array buffer.
+    fn vertex_attrib_pointers(gl: &gl::Gl);
+}
+
 #[derive(VertexAttribPointers)]
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -10,3 +22,31 @@ pub struct Vertex {
     #[location = "1"]
     pub color: f32_f32_f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::types::f32_f32;
+
+    #[derive(VertexAttribPointers)]
+    #[derive(Copy, Clone, Debug)]
+    #[repr(C, packed)]
+    struct Instance {
+        #[location = 2]
+        #[divisor = 3]
+        offset: f32_f32,
+        #[location = 3]
+        #[divisor = "2"]
+        color: f32_f32_f32,
+    }
+
+    #[test]
+    fn divisor_is_the_smallest_of_the_fields() {
+        assert_eq!(Instance::DIVISOR, 2);
+    }
+
+    #[test]
+    fn vertices_have_no_divisor() {
+        assert_eq!(Vertex::DIVISOR, 0);
+    }
+}
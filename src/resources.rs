@@ -1,7 +1,15 @@
+pub mod sources;
+pub mod loader;
+pub mod manifest;
+pub mod pak;
+pub mod image;
+pub mod fonts;
+pub mod shader_stages;
+
 use std::fs;
 use std::io;
-use std::io::Read;
 use std::ffi;
+use std::sync::Arc;
 
 use std::path::{Path, PathBuf};
 
@@ -10,83 +18,435 @@ use font_kit::font::Font;
 use font_kit::file_type::FileType;
 use font_kit::handle::Handle;
 
+pub use self::fonts::{FontFile, FontQuery, FontSource};
+pub use self::image::{Image, PixelFormat};
+pub use self::loader::{Loader, Ticket};
+pub use self::manifest::Manifest;
+pub use self::sources::{Source, DirectorySource, MemorySource, PakSource, ZipSource};
+#[cfg(feature = "embedded-assets")]
+pub use self::sources::EmbeddedSource;
+
 #[derive(Debug, Fail)]
 pub enum Error {
-    #[fail(display = "I/O Error")]
-    Io(#[cause] io::Error),
-    #[fail(display = "File is malformed(containes a null byte)")]
-    MalformedFile,
+    #[fail(display = "I/O Error reading resource {} from {:?}", name, path)]
+    Io { name: String, path: PathBuf, #[cause] inner: io::Error },
+    #[fail(display = "Resource {} at {:?} is malformed(containes a null byte)", name, path)]
+    MalformedFile { name: String, path: PathBuf },
+    #[fail(display = "Resource {} at {:?} is not a font file", name, path)]
+    InvalidFont { name: String, path: PathBuf },
+    #[fail(display = "No installed or bundled font matches {}", query)]
+    NoMatchingFont { query: String },
+    #[fail(display = "Failed to decode image {} at {:?}", name, path)]
+    InvalidImage { name: String, path: PathBuf, #[cause] inner: image::DecodeError },
+    #[fail(display = "Invalid resource name {:?}: {}", name, reason)]
+    InvalidResourceName { name: String, reason: &'static str },
     #[fail(display = "Path lookup failed")]
     NoExePath,
+    #[fail(display = "Resource {} was not found in any mounted source", name)]
+    NotFound { name: String },
+    #[fail(display = "Failed to open archive {:?}", path)]
+    Archive { path: PathBuf, #[cause] inner: zip::result::ZipError },
+    #[fail(display = "Failed to open pak archive {:?}", path)]
+    Pak { path: PathBuf, #[cause] inner: io::Error },
+    #[fail(display = "Failed to create cache directory {:?}", path)]
+    CacheDir { path: PathBuf, #[cause] inner: io::Error },
+    #[fail(display = "Failed to parse manifest {}", name)]
+    ManifestParse { name: String, #[cause] inner: ron::de::Error },
+    #[fail(display = "Manifest {} is invalid: {}", name, reason)]
+    InvalidManifest { name: String, reason: String },
+    #[fail(display = "Shader program {} mixes a compute shader with graphics stages", name)]
+    MixedShaderStages { name: String },
+    #[fail(display = "No {} named {:?} in the manifest", kind, name)]
+    UnknownAsset { kind: &'static str, name: String },
+    #[fail(display = "Resource loader stopped before finishing")]
+    LoaderStopped,
+    #[fail(display = "Resource loader job panicked: {}", message)]
+    LoaderPanicked { message: String },
 }
 
-impl From<io::Error> for Error {
-    fn from(other: io::Error) -> Self {
-        Error::Io(other)
+#[derive(Debug)]
+struct Mount {
+    point: String,
+    source: Box<dyn Source>,
+}
+
+impl Mount {
+    /// Translates a logical resource path into a path relative to this mount,
+    /// or `None` if the resource does not live below the mount point.
+    fn relative<'p>(&self, resource_name: &'p str) -> Option<&'p str> {
+        if self.point.is_empty() {
+            return Some(resource_name);
+        }
+        resource_name.strip_prefix(self.point.as_str())?.strip_prefix('/')
     }
 }
 
+/// A virtual filesystem made out of mounted sources. When more than one source
+/// contains the same resource, the one mounted last wins.
+#[derive(Debug)]
 pub struct Resources {
-    root: PathBuf,
+    mounts: Vec<Mount>,
+    cache_dir: Option<PathBuf>,
+}
+
+impl Default for Resources {
+    fn default() -> Resources {
+        Resources::new()
+    }
 }
 
 impl Resources {
-    pub fn root(&self) -> &Path {
-        &self.root
+    pub fn new() -> Resources {
+        Resources {
+            mounts: vec!(),
+            cache_dir: None,
+        }
     }
+
     pub fn from_relative_exe(path: &Path) -> Result<Resources, Error> {
         let exe_file = ::std::env::current_exe().map_err(|_| Error::NoExePath)?;
         let exe_path = exe_file.parent().ok_or(Error::NoExePath)?;
-        Ok(Resources {
-            root: exe_path.join(path)
-        })
+        let mut resources = Resources::new();
+        // Loose files override a packed archive shipped next to them.
+        let pak_path = exe_path.join(path).with_extension("pak");
+        if pak_path.is_file() {
+            resources.mount("", PakSource::open(&pak_path)?);
+        }
+        resources.mount("", DirectorySource::new(&exe_path.join(path)));
+        // Running without a cache only costs compile time, so a read-only
+        // install directory is fine.
+        let _ = resources.set_cache_dir(Some(exe_path.join("cache")));
+        // Loose files next to the executable are only used for resources the
+        // embedded table does not have.
+        #[cfg(feature = "embedded-assets")]
+        resources.mount("", EmbeddedSource::new());
+        Ok(resources)
+    }
+
+    /// Mounts a directory of loose assets over everything mounted so far,
+    /// usually the `assets` directory of a checkout. Shaders edited there
+    /// are hot reloaded and files the game writes, such as saved tweaks, go
+    /// there instead of next to the executable. Meant to be mounted last and
+    /// only on request, e.g. through `--assets-dir`.
+    pub fn mount_dev_dir(&mut self, dir: &Path) {
+        self.mount("", DirectorySource::new(dir));
+    }
+
+    /// Where derived data such as compiled program binaries is kept between
+    /// runs, `None` disables caching.
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// Moves the cache to `dir`, creating it if needed. `from_relative_exe`
+    /// puts it in `cache` next to the executable. If the directory can't be
+    /// created caching is disabled and the error returned.
+    pub fn set_cache_dir(&mut self, dir: Option<PathBuf>) -> Result<(), Error> {
+        self.cache_dir = None;
+        if let Some(dir) = dir {
+            fs::create_dir_all(&dir).map_err(|inner| Error::CacheDir {
+                path: dir.clone(),
+                inner,
+            })?;
+            self.cache_dir = Some(dir);
+        }
+        Ok(())
+    }
+
+    /// Resources served purely from the table compiled into the executable,
+    /// independent of where the executable lives.
+    #[cfg(feature = "embedded-assets")]
+    pub fn from_embedded() -> Resources {
+        let mut resources = Resources::new();
+        resources.mount("", EmbeddedSource::new());
+        resources
+    }
+
+    /// Mounts `source` so that its contents appear below `mount_point`, an
+    /// empty mount point places them at the root.
+    pub fn mount<S: Source + 'static>(&mut self, mount_point: &str, source: S) {
+        self.mounts.push(Mount {
+            point: mount_point.trim_matches('/').into(),
+            source: Box::new(source),
+        });
+    }
+
+    pub fn exists(&self, resource_name: &str) -> bool {
+        self.find(resource_name).is_ok()
+    }
+
+    pub fn load_bytes(&self, resource_name: &str) -> Result<Vec<u8>, Error> {
+        self.read(resource_name).map(|(data, _)| data)
+    }
+
+    /// Where a resource is currently served from, for error reporting.
+    pub fn locate(&self, resource_name: &str) -> Result<PathBuf, Error> {
+        let (mount, path) = self.find(resource_name)?;
+        Ok(mount.source.location(&path))
+    }
+
+    /// The file on disk a resource is currently served from, if it comes from
+    /// a mounted directory.
+    pub fn disk_path(&self, resource_name: &str) -> Option<PathBuf> {
+        self.find(resource_name)
+            .ok()
+            .and_then(|(mount, path)| mount.source.disk_path(&path))
+    }
+
+    /// Where a file for `resource_name` would go on disk, whether or not it
+    /// exists yet: below the last mounted directory that covers the name.
+    pub fn writable_path(&self, resource_name: &str) -> Option<PathBuf> {
+        let normalized = normalize_resource_name(resource_name).ok()?;
+        self.mounts.iter()
+            .rev()
+            .filter_map(|mount| mount.relative(&normalized).and_then(|path| mount.source.disk_path(path)))
+            .next()
     }
 
     pub fn load_font(&self, resource_name: &str) -> Result<Vec<Font>, Error> {
-        let mut font_handles = vec!();
-        let normalized_path = normalize_resource_path(&self.root, resource_name);
-        println!("{:?}", normalized_path);
+        let (data, path) = self.read(resource_name)?;
+        Resources::parse_font(resource_name, &path, Arc::new(data))
+    }
 
-        let mut file = fs::File::open(normalized_path.to_owned())?;
-        match Font::analyze_file(&mut file) {
-            Err(_) => panic!("Error loading font file from path: {:?}", normalized_path.to_owned()),
-            Ok(FileType::Single) => font_handles.push(Handle::from_path(normalized_path.to_owned(), 0)),
+    /// Reads a font file resource, or finds an installed font falling back to
+    /// the closest bundled one.
+    pub fn resolve_font(&self, source: &FontSource) -> Result<FontFile, Error> {
+        fonts::resolve(self, source)
+    }
+
+    /// Parses every font face out of an already loaded font file.
+    pub fn parse_font(resource_name: &str, path: &Path, data: Arc<Vec<u8>>) -> Result<Vec<Font>, Error> {
+        let invalid_font = || Error::InvalidFont {
+            name: resource_name.into(),
+            path: path.into(),
+        };
+        let mut font_handles = vec!();
+        match Font::analyze_bytes(data.clone()) {
+            Err(_) => return Err(invalid_font()),
+            Ok(FileType::Single) => font_handles.push(Handle::from_memory(data.clone(), 0)),
             Ok(FileType::Collection(font_count)) => {
                 for font_index in 0..font_count {
-                    font_handles.push(Handle::from_path(normalized_path.to_owned(), font_index))
+                    font_handles.push(Handle::from_memory(data.clone(), font_index))
                 }
             }
         }
         let mut fonts = vec!();
         for handle in &font_handles {
-            fonts.push(Font::from_handle(&handle).unwrap());
+            fonts.push(Font::from_handle(handle).map_err(|_| invalid_font())?);
         }
         Ok(fonts)
     }
-    
+
+    /// Loads and decodes a PNG, TGA or binary PPM/PGM image.
+    pub fn load_image(&self, resource_name: &str) -> Result<Image, Error> {
+        let (data, path) = self.read(resource_name)?;
+        image::decode(resource_name, &data).map_err(|inner| Error::InvalidImage {
+            name: resource_name.into(),
+            path,
+            inner,
+        })
+    }
+
+    /// Loads and validates an asset manifest.
+    pub fn load_manifest(&self, resource_name: &str) -> Result<Manifest, Error> {
+        let (data, path) = self.read(resource_name)?;
+        let text = String::from_utf8(data).map_err(|_| Error::MalformedFile {
+            name: resource_name.into(),
+            path,
+        })?;
+        let manifest = Manifest::parse(resource_name, &text)?;
+        manifest.validate(resource_name, self)?;
+        Ok(manifest)
+    }
+
     pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error> {
-        let normalized_path = normalize_resource_path(&self.root, resource_name);
-        println!("{:?}", normalized_path);
-        let mut file = fs::File::open(normalized_path)?;
-        let mut buffer: Vec<u8> = Vec::with_capacity(
-            file.metadata()?.len() as usize + 1
-        );
-        file.read_to_end(&mut buffer)?;
+        let (buffer, path) = self.read(resource_name)?;
 
         if buffer.iter().find(|i| **i == 0).is_some() {
-            return Err(Error::MalformedFile);
+            return Err(Error::MalformedFile { name: resource_name.into(), path });
         }
 
         Ok(unsafe { ffi::CString::from_vec_unchecked(buffer) })
     }
+
+    fn read(&self, resource_name: &str) -> Result<(Vec<u8>, PathBuf), Error> {
+        let (mount, path) = self.find(resource_name)?;
+        let location = mount.source.location(&path);
+        match mount.source.read(&path) {
+            Ok(data) => Ok((data, location)),
+            Err(inner) => Err(Error::Io {
+                name: resource_name.into(),
+                path: location,
+                inner,
+            }),
+        }
+    }
+
+    fn find(&self, resource_name: &str) -> Result<(&Mount, String), Error> {
+        let normalized = normalize_resource_name(resource_name)?;
+        self.mounts.iter()
+            .rev()
+            .filter_map(|mount| mount.relative(&normalized).map(|path| (mount, path.to_owned())))
+            .find(|(mount, path)| mount.source.exists(path))
+            .ok_or_else(|| Error::NotFound { name: resource_name.into() })
+    }
 }
 
-fn normalize_resource_path(root: &Path, location: &str) -> PathBuf {
-    let mut path: PathBuf = root.into();
+/// Turns a resource name into its canonical `a/b/c` form. Names are always
+/// relative to the root of the mounted sources, so absolute paths, drive
+/// letters, empty segments and `..` segments that would climb above the root
+/// are refused.
+pub fn normalize_resource_name(resource_name: &str) -> Result<String, Error> {
+    let invalid = |reason| Error::InvalidResourceName {
+        name: resource_name.into(),
+        reason,
+    };
+    if resource_name.starts_with("/") {
+        return Err(invalid("absolute paths are not allowed"));
+    }
+    if resource_name.contains("\\") {
+        return Err(invalid("path segments must be separated by '/'"));
+    }
+    let mut parts: Vec<&str> = vec!();
+    for part in resource_name.split("/") {
+        match part {
+            "" => return Err(invalid("empty path segment")),
+            "." => {},
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(invalid("path escapes the resource root"));
+                }
+            },
+            // `C:`, `C:foo` and alternate data streams such as `a:b`
+            part if parts.is_empty() && part.contains(':') => {
+                return Err(invalid("drive letters and streams are not allowed"));
+            },
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err(invalid("path does not name a file"));
+    }
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for part in location.split("/") {
-        path = path.join(part);
+    #[test]
+    fn normalizes_resource_names() {
+        let cases: &[(&str, Option<&str>)] = &[
+            ("shaders/a.frag", Some("shaders/a.frag")),
+            ("a/./b", Some("a/b")),
+            ("a/b/../c", Some("a/c")),
+            ("../../etc/passwd", None),
+            ("a/../../b", None),
+            ("/abs", None),
+            ("a//b", None),
+            ("a\\b", None),
+            ("C:/x", None),
+            ("C:foo", None),
+            ("a:b/c", None),
+            ("a/..", None),
+            (".", None),
+            ("", None),
+        ];
+        for &(name, expected) in cases {
+            assert_eq!(normalize_resource_name(name).ok().as_deref(), expected, "{:?}", name);
+        }
     }
 
-    path
-}
\ No newline at end of file
+    fn files(files: &[(&str, &str)]) -> MemorySource {
+        let mut source = MemorySource::new();
+        for &(name, text) in files {
+            source.insert(name, text);
+        }
+        source
+    }
+
+    #[test]
+    fn later_mounts_override_earlier_ones() {
+        let mut resources = Resources::new();
+        resources.mount("", files(&[("a.txt", "base"), ("b.txt", "base")]));
+        resources.mount("", files(&[("a.txt", "override")]));
+        assert_eq!(resources.load_bytes("a.txt").unwrap(), b"override");
+        // Names the later mount doesn't have still come from the earlier one
+        assert_eq!(resources.load_bytes("b.txt").unwrap(), b"base");
+    }
+
+    #[test]
+    fn mount_points_are_stripped_from_names() {
+        let mut resources = Resources::new();
+        resources.mount("", files(&[("mods/x/a.txt", "root")]));
+        resources.mount("/mods/x/", files(&[("a.txt", "mod"), ("b.txt", "mod")]));
+        assert_eq!(resources.load_bytes("mods/x/a.txt").unwrap(), b"mod");
+        assert_eq!(resources.load_bytes("mods/x/b.txt").unwrap(), b"mod");
+        assert!(resources.exists("mods/x/./b.txt"));
+        // The mount point is a whole directory, not a prefix of the name
+        assert!(!resources.exists("mods/xb.txt"));
+    }
+
+    #[test]
+    fn names_outside_a_mount_point_are_not_looked_up_in_it() {
+        let mut resources = Resources::new();
+        resources.mount("mods/x", files(&[("a.txt", "mod")]));
+        assert!(!resources.exists("a.txt"));
+        assert!(!resources.exists("mods/a.txt"));
+        match resources.load_bytes("a.txt") {
+            Err(Error::NotFound { name }) => assert_eq!(name, "a.txt"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn zip_sources_list_their_files() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("rustgl-resources-{}.zip", std::process::id()));
+        {
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+            let options = zip::write::FileOptions::default();
+            zip.add_directory("shaders/", options).unwrap();
+            zip.start_file("shaders/a.frag", options).unwrap();
+            zip.write_all(b"void main() {}").unwrap();
+            zip.finish().unwrap();
+        }
+        let mut resources = Resources::new();
+        resources.mount("", ZipSource::open(&path).unwrap());
+        let read = resources.load_bytes("shaders/a.frag");
+        let (exists, directory, missing) = (
+            resources.exists("shaders/a.frag"),
+            resources.exists("shaders"),
+            resources.exists("shaders/b.frag"),
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), b"void main() {}");
+        assert_eq!((exists, directory, missing), (true, false, false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directory_sources_do_not_follow_links_out_of_their_root() {
+        let dir = std::env::temp_dir().join(format!("rustgl-resources-{}", std::process::id()));
+        let root = dir.join("assets");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("inside.txt"), "inside").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("outside.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join("inside.txt"), root.join("alias.txt")).unwrap();
+
+        let mut resources = Resources::new();
+        resources.mount("", DirectorySource::new(&root));
+        let alias = resources.load_bytes("alias.txt");
+        let outside = resources.load_bytes("outside.txt");
+        let exists = resources.exists("outside.txt");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(alias.unwrap(), b"inside");
+        assert!(outside.is_err());
+        assert!(!exists);
+    }
+}
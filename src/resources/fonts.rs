@@ -0,0 +1,270 @@
+//! Font lookup by family, weight and style.
+//!
+//! Queries go to the system font database first (fontconfig on Linux). When
+//! none of the requested families are installed the closest bundled
+//! Digitaldream face is used instead, so a query always finds something as
+//! long as the game's own assets are mounted.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
+use font_kit::handle::Handle;
+use font_kit::properties::{Properties, Stretch, Style, Weight};
+use font_kit::source::SystemSource;
+
+use super::{Error, Resources};
+
+/// Where a font comes from.
+#[derive(Clone, Debug)]
+pub enum FontSource {
+    /// A font file resource, every face in it is used.
+    File(String),
+    /// The best installed match for a query.
+    System(FontQuery),
+}
+
+/// Families in order of preference, along with the weight, style and width
+/// to pick from the first family that is installed.
+#[derive(Clone, Debug)]
+pub struct FontQuery {
+    pub families: Vec<FamilyName>,
+    pub properties: Properties,
+}
+
+impl FontQuery {
+    /// Parses a comma separated family list in the style of CSS, e.g.
+    /// `"Inter, sans-serif"`. The generic names `serif`, `sans-serif`,
+    /// `monospace`, `cursive` and `fantasy` try a few common families.
+    pub fn new(families: &str) -> FontQuery {
+        FontQuery {
+            families: families.split(',')
+                .map(str::trim)
+                .filter(|family| !family.is_empty())
+                .map(parse_family_name)
+                .collect(),
+            properties: Properties::new(),
+        }
+    }
+
+    pub fn weight(mut self, weight: f32) -> FontQuery {
+        self.properties.weight = Weight(weight);
+        self
+    }
+
+    pub fn italic(mut self, italic: bool) -> FontQuery {
+        self.properties.style = if italic { Style::Italic } else { Style::Normal };
+        self
+    }
+
+    /// The width as a fraction of the normal width, from `0.5` for ultra
+    /// condensed to `2.0` for ultra expanded.
+    pub fn stretch(mut self, stretch: f32) -> FontQuery {
+        self.properties.stretch = Stretch(stretch);
+        self
+    }
+}
+
+fn parse_family_name(family: &str) -> FamilyName {
+    match family.to_lowercase().as_str() {
+        "serif" => FamilyName::Serif,
+        "sans-serif" => FamilyName::SansSerif,
+        "monospace" => FamilyName::Monospace,
+        "cursive" => FamilyName::Cursive,
+        "fantasy" => FamilyName::Fantasy,
+        _ => FamilyName::Title(family.into()),
+    }
+}
+
+/// The raw contents of a font file, ready to be parsed on any thread.
+#[derive(Clone, Debug)]
+pub struct FontFile {
+    /// The resource name, or the file name for system fonts.
+    pub name: String,
+    pub location: PathBuf,
+    pub bytes: Arc<Vec<u8>>,
+    /// The face to use out of a collection, `None` for every face.
+    pub face: Option<u32>,
+}
+
+impl FontFile {
+    /// Tells the faces of font files apart in the `Assets` cache. Whole
+    /// resources go by their name, so they share faces with
+    /// `Assets::font_faces`.
+    pub fn key(&self) -> String {
+        match self.face {
+            None => self.name.clone(),
+            Some(index) => format!("{}#{}", self.location.display(), index),
+        }
+    }
+
+    pub fn faces(&self) -> Result<Vec<Font>, Error> {
+        match self.face {
+            None => Resources::parse_font(&self.name, &self.location, self.bytes.clone()),
+            Some(index) => Font::from_bytes(self.bytes.clone(), index)
+                .map(|font| vec![font])
+                .map_err(|_| Error::InvalidFont {
+                    name: self.name.clone(),
+                    path: self.location.clone(),
+                }),
+        }
+    }
+}
+
+pub(super) fn resolve(resources: &Resources, source: &FontSource) -> Result<FontFile, Error> {
+    match source {
+        FontSource::File(name) => load_file(resources, name),
+        FontSource::System(query) => {
+            if let Some(file) = find_installed(query) {
+                return Ok(file);
+            }
+            for name in bundled_fallbacks(&query.properties) {
+                if resources.exists(&name) {
+                    return load_file(resources, &name);
+                }
+            }
+            Err(Error::NoMatchingFont {
+                query: format!("{:?}", query),
+            })
+        },
+    }
+}
+
+fn load_file(resources: &Resources, name: &str) -> Result<FontFile, Error> {
+    Ok(FontFile {
+        name: name.into(),
+        location: resources.locate(name)?,
+        bytes: Arc::new(resources.load_bytes(name)?),
+        face: None,
+    })
+}
+
+fn find_installed(query: &FontQuery) -> Option<FontFile> {
+    // font-kit's fontconfig source trips over families without any fonts, so
+    // only installed families are passed on, which also gives generic names
+    // more than one family to try.
+    let source = SystemSource::new();
+    let installed = source.all_families().ok()?;
+    let families: Vec<FamilyName> = query.families.iter()
+        .flat_map(family_candidates)
+        .filter_map(|candidate| installed.iter().find(|family| family.eq_ignore_ascii_case(&candidate)))
+        .map(|family| FamilyName::Title(family.clone()))
+        .collect();
+    let handle = source.select_best_match(&families, &query.properties).ok()?;
+    match handle {
+        Handle::Path { path, font_index } => {
+            // A font that can't be read is treated like one that isn't installed.
+            let bytes = fs::read(&path).ok()?;
+            Some(FontFile {
+                name: path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+                location: path,
+                bytes: Arc::new(bytes),
+                face: Some(font_index),
+            })
+        },
+        Handle::Memory { bytes, font_index } => Some(FontFile {
+            name: "<system font>".into(),
+            location: PathBuf::from("<system font>"),
+            bytes,
+            face: Some(font_index),
+        }),
+    }
+}
+
+fn family_candidates(family: &FamilyName) -> Vec<String> {
+    let names: &[&str] = match family {
+        FamilyName::Title(title) => return vec![title.clone()],
+        FamilyName::Serif => &["DejaVu Serif", "Liberation Serif", "Noto Serif", "Times New Roman"],
+        FamilyName::SansSerif => &["DejaVu Sans", "Liberation Sans", "Noto Sans", "Arial", "Helvetica"],
+        FamilyName::Monospace => &["DejaVu Sans Mono", "Liberation Mono", "Noto Sans Mono", "Courier New"],
+        FamilyName::Cursive => &["Comic Sans MS", "DejaVu Sans"],
+        FamilyName::Fantasy => &["Papyrus", "DejaVu Sans"],
+    };
+    names.iter().map(|&name| name.to_owned()).collect()
+}
+
+/// The bundled faces to try for the given properties, best match first. The
+/// width is given up first, then the slant and the weight last.
+fn bundled_fallbacks(properties: &Properties) -> Vec<String> {
+    let fat = properties.weight >= Weight::SEMIBOLD;
+    let skew = properties.style != Style::Normal;
+    let narrow = properties.stretch < Stretch::NORMAL;
+    let mut names: Vec<String> = vec!();
+    for dropped in 0..8 {
+        let name = bundled_face(
+            fat && dropped & 4 == 0,
+            skew && dropped & 2 == 0,
+            narrow && dropped & 1 == 0,
+        );
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn bundled_face(fat: bool, skew: bool, narrow: bool) -> String {
+    if !(fat || skew || narrow) {
+        return "fonts/DigitalDream.ttf".into();
+    }
+    format!(
+        "fonts/Digitaldream{}{}{}.ttf",
+        if fat { "Fat" } else { "" },
+        if skew { "Skew" } else { "" },
+        if narrow { "Narrow" } else { "" },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_list_families_in_order() {
+        let query = FontQuery::new(" Inter, ,SANS-SERIF, monospace ");
+        assert_eq!(query.families, [
+            FamilyName::Title("Inter".into()),
+            FamilyName::SansSerif,
+            FamilyName::Monospace,
+        ]);
+        assert_eq!(query.properties, Properties::new());
+        assert!(FontQuery::new("").families.is_empty());
+    }
+
+    #[test]
+    fn parses_generic_family_names() {
+        for &(family, ref expected) in &[
+            ("serif", FamilyName::Serif),
+            ("Sans-Serif", FamilyName::SansSerif),
+            ("monospace", FamilyName::Monospace),
+            ("cursive", FamilyName::Cursive),
+            ("fantasy", FamilyName::Fantasy),
+        ] {
+            assert_eq!(&parse_family_name(family), expected);
+        }
+        assert_eq!(parse_family_name("Fira Sans"), FamilyName::Title("Fira Sans".into()));
+    }
+
+    #[test]
+    fn bundled_fallbacks_give_up_width_then_slant_then_weight() {
+        let query = FontQuery::new("sans-serif").weight(700.0).italic(true).stretch(0.75);
+        assert_eq!(bundled_fallbacks(&query.properties), [
+            "fonts/DigitaldreamFatSkewNarrow.ttf",
+            "fonts/DigitaldreamFatSkew.ttf",
+            "fonts/DigitaldreamFatNarrow.ttf",
+            "fonts/DigitaldreamFat.ttf",
+            "fonts/DigitaldreamSkewNarrow.ttf",
+            "fonts/DigitaldreamSkew.ttf",
+            "fonts/DigitaldreamNarrow.ttf",
+            "fonts/DigitalDream.ttf",
+        ]);
+        assert_eq!(bundled_fallbacks(&Properties::new()), ["fonts/DigitalDream.ttf"]);
+        let narrow = FontQuery::new("serif").stretch(0.5);
+        assert_eq!(bundled_fallbacks(&narrow.properties), [
+            "fonts/DigitaldreamNarrow.ttf",
+            "fonts/DigitalDream.ttf",
+        ]);
+    }
+}
@@ -0,0 +1,365 @@
+use failure::Fail;
+
+/// How the channels of a pixel are laid out, every channel is one byte.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl PixelFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            PixelFormat::Gray => 1,
+            PixelFormat::GrayAlpha => 2,
+            PixelFormat::Rgb => 3,
+            PixelFormat::Rgba => 4,
+        }
+    }
+}
+
+/// A decoded image. Rows are tightly packed and stored top to bottom.
+#[derive(Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Fail)]
+pub enum DecodeError {
+    #[fail(display = "Image data ends early")]
+    Truncated,
+    #[fail(display = "Unsupported image: {}", _0)]
+    Unsupported(String),
+    #[fail(display = "Malformed image: {}", _0)]
+    Malformed(String),
+}
+
+/// The largest image, in bytes of decoded pixels, that is decoded. Headers can
+/// claim any size, this keeps a few bytes of a broken file from asking for
+/// gigabytes of memory.
+const MAX_IMAGE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Decodes a PNG, TGA or binary PPM/PGM image. PNG and PPM are recognised
+/// by their signature, TGA has none and is picked by the `.tga` extension.
+pub fn decode(resource_name: &str, data: &[u8]) -> Result<Image, DecodeError> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        decode_png(data)
+    } else if data.starts_with(b"P5") || data.starts_with(b"P6") {
+        decode_pnm(data)
+    } else if resource_name.to_lowercase().ends_with(".tga") {
+        decode_tga(data)
+    } else {
+        Err(DecodeError::Unsupported("unknown image format".into()))
+    }
+}
+
+fn decode_png(data: &[u8]) -> Result<Image, DecodeError> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(png_error)?;
+    let (color_type, bit_depth) = reader.output_color_type();
+    if bit_depth != png::BitDepth::Eight {
+        return Err(DecodeError::Unsupported(format!("{:?} bit PNG", bit_depth)));
+    }
+    let format = match color_type {
+        png::ColorType::Grayscale => PixelFormat::Gray,
+        png::ColorType::GrayscaleAlpha => PixelFormat::GrayAlpha,
+        png::ColorType::RGB => PixelFormat::Rgb,
+        png::ColorType::RGBA => PixelFormat::Rgba,
+        png::ColorType::Indexed => return Err(DecodeError::Unsupported("indexed PNG".into())),
+    };
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).map_err(png_error)?;
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        format,
+        pixels,
+    })
+}
+
+fn png_error(error: png::DecodingError) -> DecodeError {
+    match error {
+        png::DecodingError::IoError(_) => DecodeError::Truncated,
+        // The png crate reports running out of data as a format error
+        png::DecodingError::Format(ref message) if message.starts_with("unexpected EOF") => DecodeError::Truncated,
+        error => DecodeError::Malformed(error.to_string()),
+    }
+}
+
+fn decode_tga(data: &[u8]) -> Result<Image, DecodeError> {
+    if data.len() < 18 {
+        return Err(DecodeError::Truncated);
+    }
+    let id_length = data[0] as usize;
+    let color_map_type = data[1];
+    let image_type = data[2];
+    let color_map_length = u16::from_le_bytes([data[5], data[6]]) as usize;
+    let color_map_entry_bits = data[7] as usize;
+    let width = u16::from_le_bytes([data[12], data[13]]) as u32;
+    let height = u16::from_le_bytes([data[14], data[15]]) as u32;
+    let bits_per_pixel = data[16];
+    let descriptor = data[17];
+
+    let (format, run_length_encoded) = match (image_type, bits_per_pixel) {
+        (2, 24) => (PixelFormat::Rgb, false),
+        (2, 32) => (PixelFormat::Rgba, false),
+        (3, 8) => (PixelFormat::Gray, false),
+        (10, 24) => (PixelFormat::Rgb, true),
+        (10, 32) => (PixelFormat::Rgba, true),
+        (11, 8) => (PixelFormat::Gray, true),
+        (image_type, bits_per_pixel) => {
+            return Err(DecodeError::Unsupported(format!(
+                "TGA image type {} with {} bits per pixel", image_type, bits_per_pixel
+            )));
+        }
+    };
+    if descriptor & 0x10 != 0 {
+        return Err(DecodeError::Unsupported("right-to-left TGA".into()));
+    }
+
+    let color_map_size = if color_map_type == 1 {
+        color_map_length * ((color_map_entry_bits + 7) / 8)
+    } else {
+        0
+    };
+    let pixel_data = data.get(18 + id_length + color_map_size..).ok_or(DecodeError::Truncated)?;
+    let channels = format.channels();
+    let image_size = image_size(width, height, channels)?;
+    let mut pixels = if run_length_encoded {
+        decode_tga_rle(pixel_data, image_size, channels)?
+    } else {
+        pixel_data.get(..image_size).ok_or(DecodeError::Truncated)?.to_vec()
+    };
+
+    // TGA stores BGR(A)
+    if channels >= 3 {
+        for pixel in pixels.chunks_mut(channels) {
+            pixel.swap(0, 2);
+        }
+    }
+    // and rows bottom to top unless the descriptor says otherwise
+    if descriptor & 0x20 == 0 {
+        let row_length = width as usize * channels;
+        pixels = pixels.chunks(row_length.max(1)).rev().flat_map(|row| row.iter().cloned()).collect();
+    }
+
+    Ok(Image {
+        width,
+        height,
+        format,
+        pixels,
+    })
+}
+
+/// The size in bytes of a `width` by `height` image, if it is small enough to
+/// be decoded.
+fn image_size(width: u32, height: u32, bytes_per_pixel: usize) -> Result<usize, DecodeError> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+        .filter(|&size| size <= MAX_IMAGE_BYTES)
+        .ok_or_else(|| DecodeError::Unsupported(format!("{}x{} image is too large", width, height)))
+}
+
+fn decode_tga_rle(mut data: &[u8], image_size: usize, channels: usize) -> Result<Vec<u8>, DecodeError> {
+    // Grown as runs are decoded, the header can't be trusted with the size
+    let mut pixels = vec!();
+    while pixels.len() < image_size {
+        let (&header, rest) = data.split_first().ok_or(DecodeError::Truncated)?;
+        let count = (header & 0x7f) as usize + 1;
+        if header & 0x80 != 0 {
+            let pixel = rest.get(..channels).ok_or(DecodeError::Truncated)?;
+            for _ in 0..count {
+                pixels.extend_from_slice(pixel);
+            }
+            data = &rest[channels..];
+        } else {
+            let raw = rest.get(..count * channels).ok_or(DecodeError::Truncated)?;
+            pixels.extend_from_slice(raw);
+            data = &rest[count * channels..];
+        }
+    }
+    if pixels.len() > image_size {
+        return Err(DecodeError::Malformed("TGA run crosses the end of the image".into()));
+    }
+    Ok(pixels)
+}
+
+/// Binary PGM (`P5`) and PPM (`P6`).
+fn decode_pnm(data: &[u8]) -> Result<Image, DecodeError> {
+    let format = match &data[..2] {
+        b"P5" => PixelFormat::Gray,
+        _ => PixelFormat::Rgb,
+    };
+    let mut position = 2;
+    let width = read_pnm_number(data, &mut position)?;
+    let height = read_pnm_number(data, &mut position)?;
+    let max_value = read_pnm_number(data, &mut position)?;
+    if width == 0 || height == 0 {
+        return Err(DecodeError::Malformed("image has no pixels".into()));
+    }
+    if max_value == 0 || max_value > 65535 {
+        return Err(DecodeError::Malformed(format!("invalid maximum value {}", max_value)));
+    }
+    // exactly one whitespace character separates the header from the pixels
+    match data.get(position) {
+        Some(c) if c.is_ascii_whitespace() => position += 1,
+        Some(_) => return Err(DecodeError::Malformed("expected whitespace after the header".into())),
+        None => return Err(DecodeError::Truncated),
+    }
+
+    let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+    let data_length = image_size(width, height, format.channels() * bytes_per_sample)?;
+    let raw = data.get(position..).and_then(|rest| rest.get(..data_length)).ok_or(DecodeError::Truncated)?;
+    let pixels = if bytes_per_sample == 1 {
+        raw.iter().map(|&sample| scale_sample(sample as u32, max_value)).collect()
+    } else {
+        raw.chunks(2)
+            .map(|sample| scale_sample(u16::from_be_bytes([sample[0], sample[1]]) as u32, max_value))
+            .collect()
+    };
+
+    Ok(Image {
+        width,
+        height,
+        format,
+        pixels,
+    })
+}
+
+fn scale_sample(sample: u32, max_value: u32) -> u8 {
+    ((sample.min(max_value) * 255 + max_value / 2) / max_value) as u8
+}
+
+fn read_pnm_number(data: &[u8], position: &mut usize) -> Result<u32, DecodeError> {
+    // skip whitespace and comments
+    loop {
+        match data.get(*position) {
+            Some(b'#') => {
+                while data.get(*position).map_or(false, |&c| c != b'\n') {
+                    *position += 1;
+                }
+            },
+            Some(c) if c.is_ascii_whitespace() => *position += 1,
+            Some(_) => break,
+            None => return Err(DecodeError::Truncated),
+        }
+    }
+    let start = *position;
+    while data.get(*position).map_or(false, |c| c.is_ascii_digit()) {
+        *position += 1;
+    }
+    if start == *position {
+        return Err(DecodeError::Malformed("expected a number in the header".into()));
+    }
+    std::str::from_utf8(&data[start..*position])
+        .unwrap()
+        .parse()
+        .map_err(|_| DecodeError::Malformed("number in the header is too large".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tga_header(image_type: u8, width: u16, height: u16, bits_per_pixel: u8, descriptor: u8) -> Vec<u8> {
+        let mut header = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&[bits_per_pixel, descriptor]);
+        header
+    }
+
+    fn unsupported(result: Result<Image, DecodeError>) -> String {
+        match result {
+            Err(DecodeError::Unsupported(message)) => message,
+            other => panic!("expected an unsupported image, got {:?}", other),
+        }
+    }
+
+    fn truncated(result: Result<Image, DecodeError>) {
+        match result {
+            Err(DecodeError::Truncated) => {},
+            other => panic!("expected a truncated image, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_png() {
+        let mut data = vec!();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 2, 1);
+            encoder.set_color(png::ColorType::RGBA);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        }
+        let image = decode("sprite.png", &data).unwrap();
+        assert_eq!((image.width, image.height, image.format), (2, 1, PixelFormat::Rgba));
+        assert_eq!(image.pixels, [1, 2, 3, 4, 5, 6, 7, 8]);
+        truncated(decode("sprite.png", &data[..data.len() / 2]));
+    }
+
+    #[test]
+    fn decodes_raw_tga() {
+        // Two rows of two BGR pixels, stored bottom row first
+        let mut data = tga_header(2, 2, 2, 24, 0);
+        data.extend_from_slice(&[3, 2, 1, 6, 5, 4, 9, 8, 7, 12, 11, 10]);
+        let image = decode("sprite.tga", &data).unwrap();
+        assert_eq!((image.width, image.height, image.format), (2, 2, PixelFormat::Rgb));
+        assert_eq!(image.pixels, [7, 8, 9, 10, 11, 12, 1, 2, 3, 4, 5, 6]);
+        truncated(decode("sprite.tga", &data[..data.len() - 1]));
+        truncated(decode("sprite.tga", &data[..10]));
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_tga() {
+        // A run of three gray pixels followed by one raw pixel, top to bottom
+        let mut data = tga_header(11, 2, 2, 8, 0x20);
+        data.extend_from_slice(&[0x82, 50, 0x00, 99]);
+        let image = decode("sprite.TGA", &data).unwrap();
+        assert_eq!((image.width, image.height, image.format), (2, 2, PixelFormat::Gray));
+        assert_eq!(image.pixels, [50, 50, 50, 99]);
+        truncated(decode("sprite.tga", &data[..data.len() - 1]));
+        // A run that goes past the last pixel
+        let mut data = tga_header(11, 2, 2, 8, 0x20);
+        data.extend_from_slice(&[0x84, 50]);
+        match decode("sprite.tga", &data) {
+            Err(DecodeError::Malformed(_)) => {},
+            other => panic!("expected a malformed image, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_huge_tga_headers() {
+        let mut data = tga_header(10, 65535, 65535, 32, 0);
+        data.extend_from_slice(&[0xff, 1, 2, 3, 4]);
+        assert_eq!(unsupported(decode("huge.tga", &data)), "65535x65535 image is too large");
+    }
+
+    #[test]
+    fn decodes_ppm_and_pgm() {
+        let image = decode("sprite.ppm", b"P6\n# a comment\n2 1\n255\n\x01\x02\x03\x04\x05\x06").unwrap();
+        assert_eq!((image.width, image.height, image.format), (2, 1, PixelFormat::Rgb));
+        assert_eq!(image.pixels, [1, 2, 3, 4, 5, 6]);
+        // 16 bit samples are scaled down
+        let image = decode("mask.pgm", b"P5 1 2 65535 \xff\xff\x80\x00").unwrap();
+        assert_eq!((image.width, image.height, image.format), (1, 2, PixelFormat::Gray));
+        assert_eq!(image.pixels, [255, 128]);
+        truncated(decode("sprite.ppm", b"P6\n2 1\n255\n\x01\x02\x03"));
+        truncated(decode("sprite.ppm", b"P6\n2 1"));
+    }
+
+    #[test]
+    fn rejects_unsupported_images() {
+        assert_eq!(unsupported(decode("sprite.bmp", b"BM\0\0")), "unknown image format");
+        assert_eq!(unsupported(decode("sprite.tga", &tga_header(1, 1, 1, 8, 0))), "TGA image type 1 with 8 bits per pixel");
+        assert_eq!(unsupported(decode("sprite.tga", &tga_header(2, 1, 1, 24, 0x10))), "right-to-left TGA");
+    }
+}
@@ -0,0 +1,148 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::{Error, Resources};
+
+type Job = Box<dyn FnOnce(&Resources) + Send>;
+
+/// A pool of threads that read and decode resources off the main thread.
+/// Anything that touches GL has to happen on the context thread after the
+/// ticket for the decoded data completes.
+pub struct Loader {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Loader {
+    pub fn new(resources: Arc<Resources>, threads: usize) -> Loader {
+        let (tx, rx) = channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..threads.max(1))
+            .map(|index| {
+                let rx = rx.clone();
+                let resources = resources.clone();
+                thread::Builder::new()
+                    .name(format!("resource-loader-{}", index))
+                    .spawn(move || loop {
+                        let job = match rx.lock().unwrap().recv() {
+                            Ok(job) => job,
+                            Err(_) => break,
+                        };
+                        job(&resources);
+                    })
+                    .expect("failed to spawn resource loader thread")
+            })
+            .collect();
+        Loader {
+            jobs: Some(tx),
+            workers,
+        }
+    }
+
+    /// Queues `load` to run on one of the loader threads. A panicking job
+    /// completes its ticket with `Error::LoaderPanicked` and leaves the
+    /// thread running.
+    pub fn load<T, F>(&self, load: F) -> Ticket<T>
+    where T: Send + 'static, F: FnOnce(&Resources) -> Result<T, Error> + Send + 'static {
+        let (tx, rx) = channel();
+        let job: Job = Box::new(move |resources| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| load(resources)))
+                .unwrap_or_else(|payload| Err(Error::LoaderPanicked { message: panic_message(payload) }));
+            let _ = tx.send(result);
+        });
+        if let Some(ref jobs) = self.jobs {
+            let _ = jobs.send(job);
+        }
+        Ticket {
+            result: Some(rx),
+        }
+    }
+
+    pub fn load_bytes(&self, resource_name: &str) -> Ticket<Vec<u8>> {
+        let resource_name = resource_name.to_owned();
+        self.load(move |resources| resources.load_bytes(&resource_name))
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("unknown cause", |message| *message).into(),
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // Closing the queue lets the workers finish what they have and exit.
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The pending result of a job queued on a `Loader`.
+pub struct Ticket<T> {
+    result: Option<Receiver<Result<T, Error>>>,
+}
+
+impl<T> Ticket<T> {
+    /// Returns the result once the job is done, `None` while it is still
+    /// running or after the result has already been taken.
+    pub fn poll(&mut self) -> Option<Result<T, Error>> {
+        let result = match self.result {
+            Some(ref rx) => match rx.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => Err(Error::LoaderStopped),
+            },
+            None => return None,
+        };
+        self.result = None;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait<T>(ticket: &mut Ticket<T>) -> Result<T, Error> {
+        let start = Instant::now();
+        loop {
+            if let Some(result) = ticket.poll() {
+                return result;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "loader job never finished");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn a_panicking_job_fails_its_ticket_only() {
+        let loader = Loader::new(Arc::new(Resources::new()), 1);
+        let mut panicking = loader.load(|_| -> Result<(), Error> { panic!("broken asset") });
+        let mut next = loader.load(|_| Ok(7));
+        match wait(&mut panicking) {
+            Err(Error::LoaderPanicked { message }) => assert_eq!(message, "broken asset"),
+            other => panic!("{:?}", other),
+        }
+        // The only worker is still around to run the next job
+        assert_eq!(wait(&mut next).unwrap(), 7);
+        assert!(panicking.poll().is_none());
+    }
+
+    #[test]
+    fn errors_are_passed_on() {
+        let loader = Loader::new(Arc::new(Resources::new()), 2);
+        let mut missing = loader.load_bytes("missing.txt");
+        match wait(&mut missing) {
+            Err(Error::NotFound { name }) => assert_eq!(name, "missing.txt"),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use super::{shader_stages, Error, FontQuery, FontSource, Resources};
+
+/// Every named asset the game knows about, as declared in `manifest.ron`.
+///
+/// ```ron
+/// (
+///     programs: {
+///         "triangle": (path: "shaders/triangle_test"),
+///         "lit": (path: "shaders/mesh", defines: {"USE_TEXTURE": "1", "MAX_LIGHTS": "8"}),
+///     },
+///     fonts: {
+///         "default": (path: "fonts/DigitalDream.ttf", size: 32.0, chars: Some("0123456789")),
+///         "ui": (family: "Inter, sans-serif", weight: 600.0, stretch: 0.875, size: 16.0),
+///     },
+///     textures: {
+///         "crate": (path: "textures/crate.png"),
+///     },
+/// )
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub programs: BTreeMap<String, ProgramEntry>,
+    #[serde(default)]
+    pub fonts: BTreeMap<String, FontEntry>,
+    #[serde(default)]
+    pub textures: BTreeMap<String, TextureEntry>,
+}
+
+/// A shader program, `path` is the shared name of its stage files without
+/// extension, e.g. `shaders/triangle_test`. Either `.vert` and `.frag` or a
+/// lone `.comp` must exist. `defines` are injected into
+/// every stage, so several entries can share one set of files.
+#[derive(Debug, Deserialize)]
+pub struct ProgramEntry {
+    pub path: String,
+    #[serde(default)]
+    pub defines: BTreeMap<String, String>,
+}
+
+/// A font is either a bundled file given by `path`, or looked up among the
+/// installed fonts by `family`, `weight`, `italic` and `stretch`. `path` and `family`
+/// can be given as plain strings or wrapped in `Some(...)`.
+#[derive(Debug, Deserialize)]
+pub struct FontEntry {
+    #[serde(default, deserialize_with = "bare_or_optional")]
+    pub path: Option<String>,
+    /// Comma separated families in order of preference, see `FontQuery::new`.
+    #[serde(default, deserialize_with = "bare_or_optional")]
+    pub family: Option<String>,
+    #[serde(default = "default_font_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub italic: bool,
+    /// The width as a fraction of the normal width, see `FontQuery::stretch`.
+    #[serde(default = "default_font_stretch")]
+    pub stretch: f32,
+    pub size: f32,
+    /// Characters to rasterize, all printable ASCII when left out.
+    #[serde(default)]
+    pub chars: Option<String>,
+}
+
+fn default_font_weight() -> f32 {
+    400.0
+}
+
+fn default_font_stretch() -> f32 {
+    1.0
+}
+
+/// Reads `"text"` as well as `Some("text")` and `None`, ron only takes the
+/// latter for an `Option`.
+fn bare_or_optional<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where D: serde::Deserializer<'de> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BareOrOptional {
+        Bare(String),
+        Optional(Option<String>),
+    }
+    Ok(match BareOrOptional::deserialize(deserializer)? {
+        BareOrOptional::Bare(text) => Some(text),
+        BareOrOptional::Optional(text) => text,
+    })
+}
+
+impl FontEntry {
+    pub fn source(&self) -> FontSource {
+        match (&self.path, &self.family) {
+            (Some(path), _) => FontSource::File(path.clone()),
+            (None, family) => FontSource::System(
+                FontQuery::new(family.as_ref().map_or("", String::as_str))
+                    .weight(self.weight)
+                    .italic(self.italic)
+                    .stretch(self.stretch)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TextureEntry {
+    pub path: String,
+}
+
+impl Manifest {
+    pub fn parse(resource_name: &str, text: &str) -> Result<Manifest, Error> {
+        ron::de::from_str(text).map_err(|inner| Error::ManifestParse {
+            name: resource_name.into(),
+            inner,
+        })
+    }
+
+    /// Makes sure everything the manifest declares can actually be loaded.
+    pub fn validate(&self, resource_name: &str, resources: &Resources) -> Result<(), Error> {
+        let invalid = |reason: String| Error::InvalidManifest {
+            name: resource_name.into(),
+            reason,
+        };
+        for (name, program) in &self.programs {
+            let stage_files = match shader_stages::stage_files(resources, &program.path) {
+                Ok(stage_files) => stage_files,
+                Err(Error::MixedShaderStages { .. }) => {
+                    return Err(invalid(format!("program {:?} mixes compute and graphics stages", name)));
+                },
+                Err(e) => return Err(invalid(format!("program {:?}: {}", name, e))),
+            };
+            if let Some(missing) = stage_files.iter().find(|stage_file| !resources.exists(stage_file)) {
+                return Err(invalid(format!("program {:?} is missing {}", name, missing)));
+            }
+        }
+        for (name, font) in &self.fonts {
+            match (&font.path, &font.family) {
+                (Some(path), None) => if !resources.exists(path) {
+                    return Err(invalid(format!("font {:?} is missing {}", name, path)));
+                },
+                (None, Some(family)) => if family.trim().is_empty() {
+                    return Err(invalid(format!("font {:?} has an empty family", name)));
+                },
+                _ => return Err(invalid(format!("font {:?} needs either a path or a family", name))),
+            }
+            if !(font.weight >= 1.0 && font.weight <= 1000.0) {
+                return Err(invalid(format!("font {:?} has invalid weight {}", name, font.weight)));
+            }
+            if !(font.stretch >= 0.5 && font.stretch <= 2.0) {
+                return Err(invalid(format!("font {:?} has invalid stretch {}", name, font.stretch)));
+            }
+            if !(font.size.is_finite() && font.size > 0.0) {
+                return Err(invalid(format!("font {:?} has invalid size {}", name, font.size)));
+            }
+            if font.chars.as_ref().map_or(false, |chars| chars.is_empty()) {
+                return Err(invalid(format!("font {:?} has an empty character set", name)));
+            }
+        }
+        for (name, texture) in &self.textures {
+            if !resources.exists(&texture.path) {
+                return Err(invalid(format!("texture {:?} is missing {}", name, texture.path)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn program(&self, name: &str) -> Result<&ProgramEntry, Error> {
+        self.programs.get(name).ok_or_else(|| Error::UnknownAsset { kind: "program", name: name.into() })
+    }
+
+    pub fn font(&self, name: &str) -> Result<&FontEntry, Error> {
+        self.fonts.get(name).ok_or_else(|| Error::UnknownAsset { kind: "font", name: name.into() })
+    }
+
+    pub fn texture(&self, name: &str) -> Result<&TextureEntry, Error> {
+        self.textures.get(name).ok_or_else(|| Error::UnknownAsset { kind: "texture", name: name.into() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use font_kit::properties::Stretch;
+
+    use crate::resources::MemorySource;
+
+    fn resources(files: &[&str]) -> Resources {
+        let mut source = MemorySource::new();
+        for &file in files {
+            source.insert(file, "");
+        }
+        let mut resources = Resources::new();
+        resources.mount("", source);
+        resources
+    }
+
+    fn validate(files: &[&str]) -> Result<(), String> {
+        let manifest = Manifest::parse("manifest.ron", r#"(programs: {"p": (path: "shaders/p")})"#).unwrap();
+        manifest.validate("manifest.ron", &resources(files)).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn programs_need_a_vertex_and_a_fragment_stage() {
+        assert!(validate(&["shaders/p.vert", "shaders/p.frag"]).is_ok());
+        assert!(validate(&["shaders/p.vert", "shaders/p.geom", "shaders/p.frag"]).is_ok());
+        let missing = validate(&["shaders/p.vert"]).unwrap_err();
+        assert!(missing.contains("missing shaders/p.frag"), "{}", missing);
+    }
+
+    #[test]
+    fn compute_programs_stand_alone() {
+        assert!(validate(&["shaders/p.comp"]).is_ok());
+        let mixed = validate(&["shaders/p.comp", "shaders/p.tese"]).unwrap_err();
+        assert!(mixed.contains("mixes compute and graphics stages"), "{}", mixed);
+    }
+
+    #[test]
+    fn font_paths_and_families_can_be_bare_strings() {
+        let manifest = Manifest::parse("manifest.ron", r#"(fonts: {
+            "bare": (path: "fonts/a.ttf", size: 12.0),
+            "wrapped": (path: Some("fonts/a.ttf"), size: 12.0),
+            "family": (family: "sans-serif", size: 12.0),
+            "none": (path: None, family: Some("monospace"), size: 12.0),
+        })"#).unwrap();
+        assert_eq!(manifest.fonts["bare"].path, Some("fonts/a.ttf".into()));
+        assert_eq!(manifest.fonts["wrapped"].path, Some("fonts/a.ttf".into()));
+        assert_eq!(manifest.fonts["family"].family, Some("sans-serif".into()));
+        assert_eq!(manifest.fonts["none"].path, None);
+        assert_eq!(manifest.fonts["none"].family, Some("monospace".into()));
+    }
+
+    #[test]
+    fn font_stretch_defaults_to_normal_and_is_checked() {
+        let manifest = Manifest::parse("manifest.ron", r#"(fonts: {
+            "normal": (family: "sans-serif", size: 12.0),
+            "narrow": (family: "sans-serif", stretch: 0.75, size: 12.0),
+        })"#).unwrap();
+        assert_eq!(manifest.fonts["normal"].stretch, 1.0);
+        match manifest.fonts["narrow"].source() {
+            FontSource::System(query) => assert_eq!(query.properties.stretch, Stretch::CONDENSED),
+            source => panic!("{:?}", source),
+        }
+        manifest.validate("manifest.ron", &resources(&[])).unwrap();
+
+        let manifest = Manifest::parse("manifest.ron", r#"(fonts: {
+            "wide": (family: "sans-serif", stretch: 3.0, size: 12.0),
+        })"#).unwrap();
+        let error = manifest.validate("manifest.ron", &resources(&[])).unwrap_err().to_string();
+        assert!(error.contains("invalid stretch 3"), "{}", error);
+    }
+
+    #[test]
+    fn bundled_manifest_parses() {
+        let text = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/manifest.ron"));
+        Manifest::parse("manifest.ron", text).unwrap();
+    }
+}
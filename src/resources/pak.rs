@@ -0,0 +1,343 @@
+//! A simple indexed archive for shipping assets as a single file.
+//!
+//! All integers are little endian.
+//!
+//! ```text
+//! header      magic "RPAK", version: u32, entry count: u32, toc offset: u64
+//! blobs       the (optionally deflate compressed) contents of every entry
+//! toc         per entry: name length: u16, name: utf-8 bytes,
+//!             offset: u64, stored size: u64, size: u64, compression: u8
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+const MAGIC: &[u8; 4] = b"RPAK";
+const VERSION: u32 = 1;
+const HEADER_LEN: u64 = 4 + 4 + 4 + 8;
+/// Deflate can't compress better than this, so larger sizes of compressed
+/// entries can only come from a corrupt table of contents.
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+impl Compression {
+    fn from_u8(value: u8) -> io::Result<Compression> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(invalid_data(format!("unknown compression method {}", value))),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PakEntry {
+    pub name: String,
+    pub offset: u64,
+    pub stored_size: u64,
+    pub size: u64,
+    pub compression: Compression,
+}
+
+/// Reads entries out of a pak archive. Only the table of contents is read up
+/// front, entry contents are read on demand.
+pub struct PakReader<R> {
+    reader: R,
+    entries: HashMap<String, PakEntry>,
+}
+
+impl<R: Read + Seek> PakReader<R> {
+    pub fn new(mut reader: R) -> io::Result<PakReader<R>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a pak archive".into()));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported pak version {}", version)));
+        }
+        let entry_count = read_u32(&mut reader)?;
+        let toc_offset = read_u64(&mut reader)?;
+        let archive_len = reader.seek(SeekFrom::End(0))?;
+
+        reader.seek(SeekFrom::Start(toc_offset))?;
+        let mut entries = HashMap::new();
+        for _ in 0..entry_count {
+            let name_len = read_u16(&mut reader)? as usize;
+            let mut name = vec![0u8; name_len];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|_| invalid_data("entry name is not valid UTF-8".into()))?;
+            let entry = PakEntry {
+                offset: read_u64(&mut reader)?,
+                stored_size: read_u64(&mut reader)?,
+                size: read_u64(&mut reader)?,
+                compression: Compression::from_u8(read_u8(&mut reader)?)?,
+                name: name.clone(),
+            };
+            let in_bounds = entry.offset >= HEADER_LEN && entry.offset
+                .checked_add(entry.stored_size)
+                .map_or(false, |end| end <= toc_offset && end <= archive_len);
+            if !in_bounds {
+                return Err(invalid_data(format!("entry {} lies outside of the archive", name)));
+            }
+            let max_size = match entry.compression {
+                Compression::None => entry.stored_size,
+                Compression::Deflate => entry.stored_size.saturating_mul(MAX_DEFLATE_RATIO),
+            };
+            if entry.size > max_size || (entry.compression == Compression::None && entry.size != entry.stored_size) {
+                return Err(invalid_data(format!("entry {} claims {} bytes but stores {}", name, entry.size, entry.stored_size)));
+            }
+            entries.insert(name, entry);
+        }
+
+        Ok(PakReader {
+            reader,
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &PakEntry> {
+        self.entries.values()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub fn read(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = match self.entries.get(name) {
+            Some(entry) => entry,
+            None => return Err(io::ErrorKind::NotFound.into()),
+        };
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        let stored = (&mut self.reader).take(entry.stored_size);
+        let mut data = Vec::with_capacity(entry.size as usize);
+        match entry.compression {
+            Compression::None => { stored.take(entry.size).read_to_end(&mut data)?; },
+            Compression::Deflate => { DeflateDecoder::new(stored).take(entry.size).read_to_end(&mut data)?; },
+        }
+        if data.len() as u64 != entry.size {
+            return Err(invalid_data(format!("entry {} is truncated", name)));
+        }
+        Ok(data)
+    }
+}
+
+/// Writes a pak archive, entries are streamed out as they are added and the
+/// table of contents is written by `finish`.
+pub struct PakWriter<W> {
+    writer: W,
+    entries: Vec<PakEntry>,
+    offset: u64,
+}
+
+impl<W: Write + Seek> PakWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<PakWriter<W>> {
+        // The header is written again with the real values once the table of
+        // contents has a place.
+        write_header(&mut writer, 0, 0)?;
+        Ok(PakWriter {
+            writer,
+            entries: vec!(),
+            offset: HEADER_LEN,
+        })
+    }
+
+    /// Adds an entry. Compressed entries are stored uncompressed when
+    /// compressing does not make them any smaller.
+    pub fn add(&mut self, name: &str, data: &[u8], compression: Compression) -> io::Result<()> {
+        if name.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "entry name is too long"));
+        }
+        let compressed = match compression {
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(vec!(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Some(encoder.finish()?).filter(|compressed| compressed.len() < data.len())
+            },
+            Compression::None => None,
+        };
+        let (stored, compression) = match compressed {
+            Some(ref compressed) => (&compressed[..], Compression::Deflate),
+            None => (data, Compression::None),
+        };
+        self.writer.write_all(stored)?;
+        self.entries.push(PakEntry {
+            name: name.into(),
+            offset: self.offset,
+            stored_size: stored.len() as u64,
+            size: data.len() as u64,
+            compression,
+        });
+        self.offset += stored.len() as u64;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let toc_offset = self.offset;
+        for entry in &self.entries {
+            self.writer.write_all(&(entry.name.len() as u16).to_le_bytes())?;
+            self.writer.write_all(entry.name.as_bytes())?;
+            self.writer.write_all(&entry.offset.to_le_bytes())?;
+            self.writer.write_all(&entry.stored_size.to_le_bytes())?;
+            self.writer.write_all(&entry.size.to_le_bytes())?;
+            self.writer.write_all(&[entry.compression.to_u8()])?;
+        }
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.entries.len() as u32, toc_offset)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn write_header<W: Write>(writer: &mut W, entry_count: u32, toc_offset: u64) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&entry_count.to_le_bytes())?;
+    writer.write_all(&toc_offset.to_le_bytes())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buffer = [0u8; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buffer = [0u8; 2];
+    reader.read_exact(&mut buffer)?;
+    Ok(u16::from_le_bytes(buffer))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buffer = [0u8; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn pack(entries: &[(&str, &[u8], Compression)]) -> Vec<u8> {
+        let mut writer = PakWriter::new(Cursor::new(vec!())).unwrap();
+        for &(name, data, compression) in entries {
+            writer.add(name, data, compression).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn open(bytes: Vec<u8>) -> io::Result<PakReader<Cursor<Vec<u8>>>> {
+        PakReader::new(Cursor::new(bytes))
+    }
+
+    fn error_message<T>(result: io::Result<T>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    /// Where the fields of the first entry of a single entry archive named
+    /// `name` start.
+    fn first_entry_field(bytes: &[u8], name: &str) -> usize {
+        let mut toc_offset = [0; 8];
+        toc_offset.copy_from_slice(&bytes[12..20]);
+        u64::from_le_bytes(toc_offset) as usize + 2 + name.len()
+    }
+
+    #[test]
+    fn reads_back_what_was_packed() {
+        let text = "void main() {}\n".repeat(64);
+        let noise = (0..255u8).collect::<Vec<u8>>();
+        let bytes = pack(&[
+            ("shaders/triangle.vert", text.as_bytes(), Compression::Deflate),
+            ("noise.bin", &noise, Compression::Deflate),
+            ("empty", b"", Compression::None),
+        ]);
+        let mut reader = open(bytes).unwrap();
+        assert_eq!(reader.entries().count(), 3);
+        let compression = |reader: &PakReader<_>, name: &str| reader.entries().find(|entry| entry.name == name).unwrap().compression;
+        assert_eq!(compression(&reader, "shaders/triangle.vert"), Compression::Deflate);
+        // Compressing doesn't pay off, so it's stored as it is
+        assert_eq!(compression(&reader, "noise.bin"), Compression::None);
+        assert_eq!(reader.read("shaders/triangle.vert").unwrap(), text.as_bytes());
+        assert_eq!(reader.read("noise.bin").unwrap(), noise);
+        assert!(reader.read("empty").unwrap().is_empty());
+        assert!(reader.contains("noise.bin"));
+        assert_eq!(reader.read("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let bytes = pack(&[("a", b"abc", Compression::None)]);
+        for len in &[0, 3, 7, 19] {
+            assert_eq!(open(bytes[..*len].to_vec()).err().map(|e| e.kind()), Some(io::ErrorKind::UnexpectedEof));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_versions() {
+        let mut bytes = pack(&[("a", b"abc", Compression::None)]);
+        bytes[4] = 2;
+        assert_eq!(error_message(open(bytes.clone())), "unsupported pak version 2");
+        bytes[0] = b'Z';
+        assert_eq!(error_message(open(bytes)), "not a pak archive");
+    }
+
+    #[test]
+    fn rejects_entries_outside_of_the_archive() {
+        let mut bytes = pack(&[("a", b"abc", Compression::None)]);
+        let offset = first_entry_field(&bytes, "a");
+        bytes[offset..offset + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert_eq!(error_message(open(bytes)), "entry a lies outside of the archive");
+    }
+
+    #[test]
+    fn rejects_sizes_the_stored_data_cant_hold() {
+        let text = "aaaa".repeat(64);
+        for &compression in &[Compression::None, Compression::Deflate] {
+            let mut bytes = pack(&[("a", text.as_bytes(), compression)]);
+            let size = first_entry_field(&bytes, "a") + 16;
+            bytes[size..size + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+            assert!(error_message(open(bytes)).starts_with("entry a claims 1099511627776 bytes"));
+        }
+    }
+
+    #[test]
+    fn reports_truncated_entries() {
+        let text = "aaaa".repeat(64);
+        let mut bytes = pack(&[("a", text.as_bytes(), Compression::Deflate)]);
+        let size = first_entry_field(&bytes, "a") + 16;
+        bytes[size..size + 8].copy_from_slice(&(text.len() as u64 + 1).to_le_bytes());
+        assert_eq!(error_message(open(bytes).unwrap().read("a")), "entry a is truncated");
+    }
+}
@@ -0,0 +1,50 @@
+//! Which files make up a shader program.
+//!
+//! A program named `shaders/mesh` is made of `shaders/mesh.vert`,
+//! `shaders/mesh.frag` and whichever of the other stages exist next to them.
+//! This only looks at file names, compiling the stages is up to
+//! `render::shaders`.
+
+use super::{Error, Resources};
+
+/// Every shader stage by file extension, in pipeline order.
+pub const EXTENSIONS: [&str; 6] = [".vert", ".tesc", ".tese", ".geom", ".frag", ".comp"];
+
+/// The stages every graphics program needs.
+pub const REQUIRED_GRAPHICS: [&str; 2] = [".vert", ".frag"];
+
+/// The extension of a compute shader, which makes up a program on its own.
+pub const COMPUTE: &str = ".comp";
+
+/// The stage extension `resource_name` ends with, if any.
+pub fn extension(resource_name: &str) -> Option<&'static str> {
+    EXTENSIONS.iter()
+        .find(|&&file_extension| resource_name.ends_with(file_extension))
+        .cloned()
+}
+
+/// The stage files that make up the program `name`. A compute shader has to
+/// stand on its own, otherwise `.vert` and `.frag` are required and the
+/// other graphics stages are picked up when present.
+pub fn stage_files(resources: &Resources, name: &str) -> Result<Vec<String>, Error> {
+    let present = EXTENSIONS.iter()
+        .map(|file_extension| format!("{}{}", name, file_extension))
+        .filter(|resource_name| resources.exists(resource_name))
+        .collect::<Vec<_>>();
+    let compute = format!("{}{}", name, COMPUTE);
+    if present.contains(&compute) {
+        if present.len() > 1 {
+            return Err(Error::MixedShaderStages { name: name.into() });
+        }
+        return Ok(present);
+    }
+    // Missing required stages are still listed so loading them reports
+    // which file is missing.
+    Ok(EXTENSIONS.iter()
+        .map(|&file_extension| (file_extension, format!("{}{}", name, file_extension)))
+        .filter(|&(file_extension, ref resource_name)| {
+            REQUIRED_GRAPHICS.contains(&file_extension) || present.contains(resource_name)
+        })
+        .map(|(_, resource_name)| resource_name)
+        .collect())
+}
@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::pak::PakReader;
+use super::{normalize_resource_name, Error};
+
+/// Something that can hand out the bytes of a resource by its logical path,
+/// e.g. `shaders/triangle_test.vert`. Paths are always `/` separated, already
+/// normalized and relative to wherever the source is mounted.
+pub trait Source: fmt::Debug + Send + Sync {
+    fn exists(&self, path: &str) -> bool;
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Where `path` lives, used to tell which file failed in errors.
+    fn location(&self, path: &str) -> PathBuf;
+
+    /// The file on disk backing `path`, for sources that are plain files.
+    fn disk_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Files below a directory. Reads follow symbolic links only as far as they
+/// stay below the directory, `disk_path` does not look at links.
+#[derive(Debug)]
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(root: &Path) -> DirectorySource {
+        DirectorySource {
+            root: root.into(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl DirectorySource {
+    /// The file for `path`, provided it exists and is still below the root
+    /// once symbolic links are followed.
+    fn existing_file(&self, path: &str) -> io::Result<PathBuf> {
+        let path = resolve_resource_path(&self.root, path)?;
+        if !path.canonicalize()?.starts_with(self.root.canonicalize()?) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "resource links to a file outside of its directory"));
+        }
+        Ok(path)
+    }
+}
+
+impl Source for DirectorySource {
+    fn exists(&self, path: &str) -> bool {
+        match self.existing_file(path) {
+            Ok(path) => path.is_file(),
+            Err(_) => false,
+        }
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let mut file = fs::File::open(self.existing_file(path)?)?;
+        let mut buffer: Vec<u8> = Vec::with_capacity(
+            file.metadata()?.len() as usize + 1
+        );
+        file.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn location(&self, path: &str) -> PathBuf {
+        resolve_resource_path(&self.root, path).unwrap_or_else(|_| self.root.clone())
+    }
+
+    fn disk_path(&self, path: &str) -> Option<PathBuf> {
+        resolve_resource_path(&self.root, path).ok()
+    }
+}
+
+/// Resources held in memory, mostly useful for tests and generated content.
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> MemorySource {
+        MemorySource::default()
+    }
+
+    pub fn insert<B: Into<Vec<u8>>>(&mut self, path: &str, data: B) {
+        self.files.insert(path.into(), data.into());
+    }
+
+    pub fn with_file<B: Into<Vec<u8>>>(mut self, path: &str, data: B) -> MemorySource {
+        self.insert(path, data);
+        self
+    }
+}
+
+impl Source for MemorySource {
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files.get(path)
+            .cloned()
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn location(&self, path: &str) -> PathBuf {
+        PathBuf::from("<memory>").join(path)
+    }
+}
+
+/// Resources stored inside a zip archive. The archive is opened once and kept
+/// open, entries are looked up by their name inside the archive.
+pub struct ZipSource {
+    path: PathBuf,
+    archive: Mutex<zip::ZipArchive<fs::File>>,
+    /// Every file in the archive, so `exists` doesn't have to lock it.
+    names: HashSet<String>,
+}
+
+impl ZipSource {
+    pub fn open(path: &Path) -> Result<ZipSource, Error> {
+        let archive = fs::File::open(path)
+            .map_err(zip::result::ZipError::Io)
+            .and_then(zip::ZipArchive::new)
+            .map_err(|inner| Error::Archive { path: path.into(), inner })?;
+        let names = archive.file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(|name| name.to_owned())
+            .collect();
+        Ok(ZipSource {
+            path: path.into(),
+            archive: Mutex::new(archive),
+            names,
+        })
+    }
+}
+
+impl fmt::Debug for ZipSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZipSource")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Source for ZipSource {
+    fn exists(&self, path: &str) -> bool {
+        self.names.contains(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut entry = match archive.by_name(path) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::Io(e)) => return Err(e),
+            Err(zip::result::ZipError::FileNotFound) => return Err(io::ErrorKind::NotFound.into()),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let mut buffer: Vec<u8> = Vec::with_capacity(entry.size() as usize + 1);
+        entry.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn location(&self, path: &str) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+/// Resources stored inside a pak archive built by `pack-assets`.
+pub struct PakSource {
+    path: PathBuf,
+    archive: Mutex<PakReader<io::BufReader<fs::File>>>,
+}
+
+impl PakSource {
+    pub fn open(path: &Path) -> Result<PakSource, Error> {
+        let archive = fs::File::open(path)
+            .and_then(|file| PakReader::new(io::BufReader::new(file)))
+            .map_err(|inner| Error::Pak { path: path.into(), inner })?;
+        Ok(PakSource {
+            path: path.into(),
+            archive: Mutex::new(archive),
+        })
+    }
+}
+
+impl fmt::Debug for PakSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PakSource")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Source for PakSource {
+    fn exists(&self, path: &str) -> bool {
+        self.archive.lock().unwrap().contains(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.archive.lock().unwrap().read(path)
+    }
+
+    fn location(&self, path: &str) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+#[cfg(feature = "embedded-assets")]
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+}
+
+/// The contents of `assets/` compiled into the executable by `build.rs`.
+#[cfg(feature = "embedded-assets")]
+#[derive(Debug)]
+pub struct EmbeddedSource {
+    files: HashMap<&'static str, &'static [u8]>,
+}
+
+#[cfg(feature = "embedded-assets")]
+impl EmbeddedSource {
+    pub fn new() -> EmbeddedSource {
+        EmbeddedSource {
+            files: embedded::FILES.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(feature = "embedded-assets")]
+impl Source for EmbeddedSource {
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files.get(path)
+            .map(|data| data.to_vec())
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn location(&self, path: &str) -> PathBuf {
+        PathBuf::from("<embedded>").join(path)
+    }
+}
+
+/// Joins a resource path onto `root`, refusing anything that would end up
+/// outside of it.
+fn resolve_resource_path(root: &Path, location: &str) -> io::Result<PathBuf> {
+    let normalized = normalize_resource_name(location)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut path: PathBuf = root.into();
+
+    for part in normalized.split("/") {
+        path = path.join(part);
+    }
+
+    Ok(path)
+}